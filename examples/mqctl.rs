@@ -1,7 +1,8 @@
 // SPDX-License-Identifier: Apache-2.0 AND MIT
-use async_mq::{prelude::*, Error};
+use async_mq::{prelude::*, ConsumerPool, Error, ThreadPoolExecutor, TokioExecutor};
 use flatbuffers::FlatBufferBuilder;
 use futures_util::stream::StreamExt;
+use std::sync::Arc;
 
 pub enum Runtime {
     TokioThreaded,
@@ -10,7 +11,10 @@ pub enum Runtime {
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cfg = crate::cfg::Config::parse();
+    let cfg = crate::cfg::Config::parse()?;
+    if cfg.cleanup {
+        return futures::executor::block_on(cleanup(cfg));
+    }
     match cfg.runtime {
         Runtime::TokioThreaded => tokio_threaded(cfg),
         Runtime::ThreadPool => thread_pool(cfg),
@@ -18,16 +22,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 }
 
+async fn cleanup(cfg: crate::cfg::Config) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = Client::new().connect_with(&cfg.conn).await?;
+    let opts = lapin::options::QueueDeleteOptions::default();
+    let count = conn.delete_queue(&cfg.queue, opts).await?;
+    eprintln!("deleted queue '{}' with {} message(s)", cfg.queue, count);
+    let opts = lapin::options::ExchangeDeleteOptions::default();
+    conn.delete_exchange(&cfg.exchange, opts).await?;
+    eprintln!("deleted exchange '{}'", cfg.exchange);
+    Ok(())
+}
+
 fn tokio_threaded(cfg: crate::cfg::Config) -> Result<(), Box<dyn std::error::Error>> {
     let mut rt = tokio::runtime::Builder::new()
         .threaded_scheduler()
         .enable_time()
         .build()?;
-    let client = Client::new();
+    let mut client = Client::new();
+    client.task_executor(Arc::new(TokioExecutor::new(rt.handle().clone())));
 
     rt.block_on(async move {
         // One connection for multiple producers.
-        let conn = client.connect(&cfg.uri).await?;
+        let conn = client.connect_with(&cfg.conn).await?;
         let mut builder = conn.producer_builder();
         builder.exchange(&cfg.exchange).queue(&cfg.queue);
         for _ in 0..cfg.producers {
@@ -44,24 +60,12 @@ fn tokio_threaded(cfg: crate::cfg::Config) -> Result<(), Box<dyn std::error::Err
                 }
             });
         }
-        // One connection for multiple consumers.
-        let conn = client.connect(&cfg.uri).await?;
+        // One connection for the pool of consumers.
+        let conn = client.connect_with(&cfg.conn).await?;
         let mut builder = conn.consumer_builder();
         builder.exchange(&cfg.exchange).queue(&cfg.queue);
-        for _ in 0..cfg.consumers {
-            let builder = builder.clone();
-            tokio::spawn(async move {
-                match builder.build().await {
-                    Err(err) => eprintln!("{}", err),
-                    Ok(c) => {
-                        let mut c = EchoConsumer(c);
-                        if let Err(err) = c.run().await {
-                            eprintln!("{}", err);
-                        }
-                    }
-                }
-            });
-        }
+        let mut consumers = ConsumerPool::new(builder);
+        consumers.resize(cfg.consumers).await?;
         // idle loop.
         loop {
             tokio::time::delay_for(std::time::Duration::from_millis(1000)).await;
@@ -76,11 +80,12 @@ fn thread_pool(cfg: crate::cfg::Config) -> Result<(), Box<dyn std::error::Error>
     use std::thread;
 
     let pool = ThreadPool::new()?;
-    let client = Client::new();
+    let mut client = Client::new();
+    client.task_executor(Arc::new(ThreadPoolExecutor::new(pool.clone())));
 
     // One connection for multiple thread pool producers and consumers each.
-    let producer_conn = block_on(client.connect(&cfg.uri))?;
-    let consumer_conn = block_on(client.connect(&cfg.uri))?;
+    let producer_conn = block_on(client.connect_with(&cfg.conn))?;
+    let consumer_conn = block_on(client.connect_with(&cfg.conn))?;
 
     let enter = enter()?;
     let mut builder = producer_conn.producer_builder();
@@ -99,23 +104,11 @@ fn thread_pool(cfg: crate::cfg::Config) -> Result<(), Box<dyn std::error::Error>
             }
         })?;
     }
+    drop(enter);
     let mut builder = consumer_conn.consumer_builder();
     builder.exchange(&cfg.exchange).queue(&cfg.queue);
-    for _ in 0..cfg.consumers {
-        let builder = builder.clone();
-        pool.spawn(async move {
-            match builder.build().await {
-                Err(err) => eprintln!("{}", err),
-                Ok(c) => {
-                    let mut c = EchoConsumer(c);
-                    if let Err(err) = c.run().await {
-                        eprintln!("{}", err);
-                    }
-                }
-            }
-        })?;
-    }
-    drop(enter);
+    let mut consumers = ConsumerPool::new(builder);
+    block_on(consumers.resize(cfg.consumers))?;
 
     // idle loop.
     loop {
@@ -132,7 +125,7 @@ fn local_pool(cfg: crate::cfg::Config) -> Result<(), Box<dyn std::error::Error>>
     let client = Client::new();
 
     // A single connection for multiple local pool producers.
-    let conn = block_on(client.connect(&cfg.uri))?;
+    let conn = block_on(client.connect_with(&cfg.conn))?;
     let mut builder = conn.producer_builder();
     builder.exchange(&cfg.exchange).queue(&cfg.queue);
     for _ in 0..cfg.producers {
@@ -156,7 +149,7 @@ fn local_pool(cfg: crate::cfg::Config) -> Result<(), Box<dyn std::error::Error>>
     // A single connection for multiple local pool consumers.
     let consumers_per_thread = cfg.consumers_per_thread;
     let consumers = cfg.consumers / consumers_per_thread;
-    let conn = block_on(client.connect(&cfg.uri))?;
+    let conn = block_on(client.connect_with(&cfg.conn))?;
     let mut builder = conn.consumer_builder();
     builder.exchange(&cfg.exchange).queue(&cfg.queue);
     for _ in 0..consumers {
@@ -251,13 +244,14 @@ mod cfg {
     const CONSUMERS_PER_THREAD: usize = 8;
 
     pub struct Config {
-        pub uri: String,
+        pub conn: async_mq::ConnectionConfig,
         pub exchange: String,
         pub queue: String,
         pub runtime: super::Runtime,
         pub producers: usize,
         pub consumers: usize,
         pub consumers_per_thread: usize,
+        pub cleanup: bool,
     }
 
     impl std::str::FromStr for super::Runtime {
@@ -274,7 +268,7 @@ mod cfg {
     }
 
     impl Config {
-        pub fn parse() -> Self {
+        pub fn parse() -> Result<Self, Box<dyn std::error::Error>> {
             use clap::{value_t, App, Arg, SubCommand};
             let producers = PRODUCERS.to_string();
             let consumers = CONSUMERS.to_string();
@@ -375,14 +369,23 @@ mod cfg {
                                 .default_value(&consumers_per_thread),
                         ),
                 )
+                .subcommand(
+                    SubCommand::with_name("cleanup").about("Delete the queue and the exchange"),
+                )
                 .get_matches();
             let runtime = value_t!(opts, "runtime", super::Runtime).unwrap();
-            let scheme = opts.value_of("scheme").unwrap();
-            let user = opts.value_of("username").unwrap();
-            let pass = opts.value_of("password").unwrap();
-            let cluster = opts.value_of("cluster").unwrap();
-            let vhost = opts.value_of("vhost").unwrap();
-            let uri = format!("{}://{}:{}@{}/{}", scheme, user, pass, cluster, vhost);
+            let mut conn = async_mq::ConnectionConfig::new();
+            if opts.value_of("scheme") == Some("amqps") {
+                conn.scheme(lapin::uri::AMQPScheme::AMQPS);
+            }
+            conn.username(opts.value_of("username").unwrap())
+                .password(opts.value_of("password").unwrap())
+                .vhost(opts.value_of("vhost").unwrap());
+            let (host, port) = host_port(opts.value_of("cluster").unwrap())?;
+            conn.host(host);
+            if let Some(port) = port {
+                conn.port(port);
+            }
             let exchange = opts.value_of("exchange").unwrap();
             let queue = opts.value_of("queue").unwrap();
             let mut producers = PRODUCERS;
@@ -399,14 +402,70 @@ mod cfg {
                     consumers_per_thread = val;
                 }
             }
-            Self {
-                uri,
+            Ok(Self {
+                conn,
                 exchange: exchange.to_string(),
                 queue: queue.to_string(),
                 runtime,
                 producers,
                 consumers,
                 consumers_per_thread,
+                cleanup: opts.subcommand_matches("cleanup").is_some(),
+            })
+        }
+    }
+
+    /// Splits the `host[:port]` cluster address, with the optional
+    /// brackets around the IPv6 host, e.g. `[::1]:5672`.
+    fn host_port(cluster: &str) -> Result<(&str, Option<u16>), std::num::ParseIntError> {
+        let (host, port) = match cluster.rfind(':') {
+            Some(i) if !cluster[i..].contains(']') => {
+                (&cluster[..i], Some(cluster[i + 1..].parse()?))
+            }
+            _ => (cluster, None),
+        };
+        if host.starts_with('[') && host.ends_with(']') {
+            return Ok((&host[1..host.len() - 1], port));
+        }
+        Ok((host, port))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        #[test]
+        fn host_port() {
+            struct Test {
+                data: &'static str,
+                want: Option<(&'static str, Option<u16>)>,
+            }
+            let tests = [
+                Test {
+                    data: "127.0.0.1",
+                    want: Some(("127.0.0.1", None)),
+                },
+                Test {
+                    data: "127.0.0.1:5672",
+                    want: Some(("127.0.0.1", Some(5672))),
+                },
+                Test {
+                    data: "[::1]",
+                    want: Some(("::1", None)),
+                },
+                Test {
+                    data: "[::1]:5671",
+                    want: Some(("::1", Some(5671))),
+                },
+                Test {
+                    data: "127.0.0.1:amqp",
+                    want: None,
+                },
+                Test {
+                    data: "127.0.0.1:",
+                    want: None,
+                },
+            ];
+            for t in &tests {
+                assert_eq!(t.want, super::host_port(t.data).ok(), "{}", t.data);
             }
         }
     }
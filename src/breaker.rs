@@ -0,0 +1,350 @@
+// SPDX-License-Identifier: Apache-2.0 AND MIT
+//! `CircuitBreaker` struct
+use futures::channel::mpsc;
+use futures::stream::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// The default number of the consecutive failures to open the circuit.
+pub const DEFAULT_FAILURE_THRESHOLD: usize = 5;
+/// The default time the circuit stays open before the trial request.
+pub const DEFAULT_OPEN_DURATION: Duration = Duration::from_secs(30);
+/// The default number of the successful trial requests to close
+/// the circuit.
+pub const DEFAULT_HALF_OPEN_TRIALS: usize = 1;
+
+/// A [CircuitBreaker] state.
+///
+/// [CircuitBreaker]: struct.CircuitBreaker.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CircuitState {
+    /// Calling the service as usual.
+    Closed,
+    /// Failing fast with [Error::CircuitOpen].
+    ///
+    /// [Error::CircuitOpen]: ../error/enum.Error.html#variant.CircuitOpen
+    Open,
+    /// Calling the service with the trial requests, which close
+    /// the circuit on success or open it again on failure.
+    HalfOpen,
+}
+
+/// A circuit breaker wrapper around [Producer], which opens the circuit
+/// after the consecutive failures, including [Error::Timeout] of
+/// [ProducerBuilder::rpc_timeout], and fails fast with [Error::CircuitOpen]
+/// until the trial request succeeds.
+///
+/// [Producer]: ../produce/struct.Producer.html
+/// [Error::Timeout]: ../error/enum.Error.html#variant.Timeout
+/// [ProducerBuilder::rpc_timeout]: ../produce/struct.ProducerBuilder.html#method.rpc_timeout
+/// [Error::CircuitOpen]: ../error/enum.Error.html#variant.CircuitOpen
+pub struct CircuitBreaker {
+    producer: crate::Producer,
+    circuit: Circuit,
+}
+
+/// The [CircuitBreaker] state machine.
+///
+/// [CircuitBreaker]: struct.CircuitBreaker.html
+struct Circuit {
+    state: CircuitState,
+    failures: usize,
+    successes: usize,
+    opened_at: Instant,
+    threshold: usize,
+    open_duration: Duration,
+    trials: usize,
+    watchers: Vec<mpsc::UnboundedSender<CircuitState>>,
+}
+
+impl CircuitBreaker {
+    /// Wraps the [Producer] with the closed circuit.
+    ///
+    /// [Producer]: ../produce/struct.Producer.html
+    pub fn new(producer: crate::Producer) -> Self {
+        Self {
+            producer,
+            circuit: Circuit::default(),
+        }
+    }
+    /// Specify the number of the consecutive failures to open the circuit,
+    /// [DEFAULT_FAILURE_THRESHOLD] by default.
+    ///
+    /// [DEFAULT_FAILURE_THRESHOLD]: constant.DEFAULT_FAILURE_THRESHOLD.html
+    pub fn failure_threshold(&mut self, threshold: usize) -> &mut Self {
+        self.circuit.threshold = threshold;
+        self
+    }
+    /// Specify the time the circuit stays open, [DEFAULT_OPEN_DURATION]
+    /// by default.
+    ///
+    /// [DEFAULT_OPEN_DURATION]: constant.DEFAULT_OPEN_DURATION.html
+    pub fn open_duration(&mut self, duration: Duration) -> &mut Self {
+        self.circuit.open_duration = duration;
+        self
+    }
+    /// Specify the number of the successful trial requests to close
+    /// the circuit, [DEFAULT_HALF_OPEN_TRIALS] by default.
+    ///
+    /// [DEFAULT_HALF_OPEN_TRIALS]: constant.DEFAULT_HALF_OPEN_TRIALS.html
+    pub fn half_open_trials(&mut self, trials: usize) -> &mut Self {
+        self.circuit.trials = trials;
+        self
+    }
+    /// Returns the current [CircuitState].
+    ///
+    /// [CircuitState]: enum.CircuitState.html
+    pub fn state(&self) -> CircuitState {
+        self.circuit.state
+    }
+    /// Returns a stream of the [CircuitState] changes.
+    ///
+    /// [CircuitState]: enum.CircuitState.html
+    pub fn state_events(&mut self) -> CircuitEvents {
+        let (tx, rx) = mpsc::unbounded();
+        self.circuit.watchers.push(tx);
+        CircuitEvents(rx)
+    }
+    /// Returns the wrapped [Producer].
+    ///
+    /// [Producer]: ../produce/struct.Producer.html
+    pub fn into_inner(self) -> crate::Producer {
+        self.producer
+    }
+    /// Publishes the message through [Producer::publish], or fails with
+    /// [Error::CircuitOpen] while the circuit is open.  The errors count
+    /// toward opening the circuit, except the local ones, i.e.
+    /// [Error::Blocked], [Error::RateLimited] and [Error::Config].
+    ///
+    /// [Producer::publish]: ../produce/struct.Producer.html#method.publish
+    /// [Error::CircuitOpen]: ../error/enum.Error.html#variant.CircuitOpen
+    /// [Error::Blocked]: ../error/enum.Error.html#variant.Blocked
+    /// [Error::RateLimited]: ../error/enum.Error.html#variant.RateLimited
+    /// [Error::Config]: ../error/enum.Error.html#variant.Config
+    pub async fn publish(&mut self, msg: Vec<u8>) -> crate::Result<()> {
+        self.circuit.acquire()?;
+        let result = self.producer.publish(msg).await;
+        self.circuit.record(&result);
+        result
+    }
+    /// Sends the request through [Producer::rpc], or fails with
+    /// [Error::CircuitOpen] while the circuit is open.  The errors, e.g.
+    /// [Error::Timeout], count toward opening the circuit, except the local
+    /// ones, i.e. [Error::Blocked], [Error::RateLimited] and [Error::Config].
+    ///
+    /// [Producer::rpc]: ../produce/struct.Producer.html#method.rpc
+    /// [Error::CircuitOpen]: ../error/enum.Error.html#variant.CircuitOpen
+    /// [Error::Timeout]: ../error/enum.Error.html#variant.Timeout
+    /// [Error::Blocked]: ../error/enum.Error.html#variant.Blocked
+    /// [Error::RateLimited]: ../error/enum.Error.html#variant.RateLimited
+    /// [Error::Config]: ../error/enum.Error.html#variant.Config
+    pub async fn rpc(&mut self, msg: Vec<u8>) -> crate::Result<Vec<u8>> {
+        self.circuit.acquire()?;
+        let result = self.producer.rpc(msg).await;
+        self.circuit.record(&result);
+        result
+    }
+}
+
+impl Default for Circuit {
+    fn default() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            failures: 0,
+            successes: 0,
+            opened_at: Instant::now(),
+            threshold: DEFAULT_FAILURE_THRESHOLD,
+            open_duration: DEFAULT_OPEN_DURATION,
+            trials: DEFAULT_HALF_OPEN_TRIALS,
+            watchers: Vec::new(),
+        }
+    }
+}
+
+impl Circuit {
+    /// Fails fast with [Error::CircuitOpen] while the circuit is open,
+    /// or lets the trial request through after the open duration.
+    ///
+    /// [Error::CircuitOpen]: ../error/enum.Error.html#variant.CircuitOpen
+    fn acquire(&mut self) -> crate::Result<()> {
+        if self.state == CircuitState::Open {
+            if self.opened_at.elapsed() < self.open_duration {
+                return Err(crate::Error::CircuitOpen);
+            }
+            self.transit(CircuitState::HalfOpen);
+        }
+        Ok(())
+    }
+    /// Records the result of the request, ignoring the local errors, which
+    /// don't tell the service's health.
+    fn record<T>(&mut self, result: &crate::Result<T>) {
+        let success = match result {
+            Ok(_) => true,
+            Err(crate::Error::Blocked)
+            | Err(crate::Error::RateLimited)
+            | Err(crate::Error::Config(_)) => return,
+            Err(_) => false,
+        };
+        match self.state {
+            CircuitState::Closed if success => self.failures = 0,
+            CircuitState::Closed => {
+                self.failures += 1;
+                if self.failures >= self.threshold {
+                    self.transit(CircuitState::Open);
+                }
+            }
+            CircuitState::HalfOpen if success => {
+                self.successes += 1;
+                if self.successes >= self.trials {
+                    self.transit(CircuitState::Closed);
+                }
+            }
+            CircuitState::HalfOpen => self.transit(CircuitState::Open),
+            CircuitState::Open => {}
+        }
+    }
+    fn transit(&mut self, state: CircuitState) {
+        self.state = state;
+        self.failures = 0;
+        self.successes = 0;
+        if state == CircuitState::Open {
+            self.opened_at = Instant::now();
+        }
+        self.watchers.retain(|tx| tx.unbounded_send(state).is_ok());
+    }
+}
+
+/// A [Stream] of the [CircuitState] changes, returned by
+/// [CircuitBreaker::state_events].
+///
+/// [Stream]: https://docs.rs/futures/latest/futures/stream/trait.Stream.html
+/// [CircuitState]: enum.CircuitState.html
+/// [CircuitBreaker::state_events]: struct.CircuitBreaker.html#method.state_events
+pub struct CircuitEvents(mpsc::UnboundedReceiver<CircuitState>);
+
+impl Stream for CircuitEvents {
+    type Item = CircuitState;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CircuitState::{Closed, HalfOpen, Open};
+    use futures::executor::block_on;
+    use futures::stream::StreamExt;
+    use std::time::Duration;
+
+    #[test]
+    fn record() {
+        use crate::Error::{Blocked, Config, Other, RateLimited, Timeout};
+        struct Test {
+            name: &'static str,
+            open: Duration,
+            data: Vec<Option<crate::Result<()>>>,
+            want: (super::CircuitState, Vec<super::CircuitState>),
+        }
+        // None stands for the request failed fast by the open circuit.
+        let tests = [
+            Test {
+                name: "failures below threshold",
+                open: Duration::default(),
+                data: vec![
+                    Some(Err(Timeout)),
+                    Some(Err(Other)),
+                    Some(Ok(())),
+                    Some(Err(Timeout)),
+                ],
+                want: (Closed, vec![]),
+            },
+            Test {
+                name: "open on threshold",
+                open: super::DEFAULT_OPEN_DURATION,
+                data: vec![
+                    Some(Err(Timeout)),
+                    Some(Err(Timeout)),
+                    Some(Err(Timeout)),
+                    None,
+                ],
+                want: (Open, vec![Open]),
+            },
+            Test {
+                name: "ignore local errors",
+                open: super::DEFAULT_OPEN_DURATION,
+                data: vec![
+                    Some(Err(Timeout)),
+                    Some(Err(Timeout)),
+                    Some(Err(RateLimited)),
+                    Some(Err(Blocked)),
+                    Some(Err(Config(String::from("invalid")))),
+                    Some(Err(Timeout)),
+                    None,
+                ],
+                want: (Open, vec![Open]),
+            },
+            Test {
+                name: "close on trial success",
+                open: Duration::default(),
+                data: vec![
+                    Some(Err(Timeout)),
+                    Some(Err(Timeout)),
+                    Some(Err(Timeout)),
+                    Some(Ok(())),
+                ],
+                want: (Closed, vec![Open, HalfOpen, Closed]),
+            },
+            Test {
+                name: "ignore local errors on trial",
+                open: Duration::default(),
+                data: vec![
+                    Some(Err(Timeout)),
+                    Some(Err(Timeout)),
+                    Some(Err(Timeout)),
+                    Some(Err(RateLimited)),
+                    Some(Err(Blocked)),
+                    Some(Ok(())),
+                ],
+                want: (Closed, vec![Open, HalfOpen, Closed]),
+            },
+            Test {
+                name: "reopen on trial failure",
+                open: Duration::default(),
+                data: vec![
+                    Some(Err(Timeout)),
+                    Some(Err(Timeout)),
+                    Some(Err(Timeout)),
+                    Some(Err(Timeout)),
+                ],
+                want: (Open, vec![Open, HalfOpen, Open]),
+            },
+        ];
+        for t in &tests {
+            let mut circuit = super::Circuit {
+                threshold: 3,
+                open_duration: t.open,
+                ..super::Circuit::default()
+            };
+            let (tx, rx) = futures::channel::mpsc::unbounded();
+            circuit.watchers.push(tx);
+            for result in &t.data {
+                match result {
+                    Some(result) => {
+                        circuit.acquire().unwrap();
+                        circuit.record(result);
+                    }
+                    None => assert_eq!(
+                        Err(crate::Error::CircuitOpen),
+                        circuit.acquire(),
+                        "{}",
+                        t.name
+                    ),
+                }
+            }
+            circuit.watchers.clear();
+            let events: Vec<_> = block_on(rx.collect());
+            assert_eq!(t.want, (circuit.state, events), "{}", t.name);
+        }
+    }
+}
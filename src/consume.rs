@@ -1,8 +1,88 @@
 // SPDX-License-Identifier: Apache-2.0 AND MIT
 //! `ConsumerBuilder` and `Consumer` structs
+use futures::future::{self, Either};
 use futures::stream::{Stream, StreamExt};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// The default consumer tag.
+pub const DEFAULT_CONSUMER_TAG: &str = "consumer";
+/// The default prefetch count of the stream queue consumers, which
+/// requires the prefetch count.
+pub const DEFAULT_STREAM_PREFETCH: u16 = 100;
+/// The default maximum number of messages in a batch.
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+/// The default maximum time to wait for a batch to fill up.
+pub const DEFAULT_BATCH_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// A queue type, declared through the `x-queue-type` argument.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QueueType {
+    Classic,
+    /// Replicated, durable queue, with the poison message handling
+    /// through the delivery limit.
+    Quorum,
+    /// Replicated, durable and append-only log, which requires the
+    /// consumer prefetch count and the manual acks.
+    Stream,
+}
+
+/// A queue overflow behavior, once the queue reaches the maximum length.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    /// Drop or dead-letter the oldest messages.
+    DropHead,
+    /// Reject the new messages, which the publisher observes through
+    /// [Error::Unconfirmed] with the publisher confirms.
+    ///
+    /// [Error::Unconfirmed]: ../error/enum.Error.html#variant.Unconfirmed
+    RejectPublish,
+    /// Reject and dead-letter the new messages.
+    RejectPublishDlx,
+}
+
+impl Overflow {
+    /// Returns the `x-overflow` argument value.
+    pub(crate) fn value(self) -> lapin::types::AMQPValue {
+        let overflow = match self {
+            Self::DropHead => "drop-head",
+            Self::RejectPublish => "reject-publish",
+            Self::RejectPublishDlx => "reject-publish-dlx",
+        };
+        lapin::types::AMQPValue::LongString(overflow.into())
+    }
+}
+
+/// A stream queue offset, from which the consumer starts to consume.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamOffset {
+    /// Start from the first available message.
+    First,
+    /// Start from the last written chunk of messages.
+    Last,
+    /// Start from the next message written after the consumer is started.
+    Next,
+    /// Start from the messages written after the UNIX timestamp
+    /// in seconds.
+    Timestamp(u64),
+    /// Start from the exact offset.
+    Offset(u64),
+}
+
+/// A [Consumer] scheduling among the subscribed queues, while more than
+/// one of them have messages ready.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Scheduling {
+    /// Take turns among the queues.
+    #[default]
+    RoundRobin,
+    /// Prefer the earlier subscribed queue over the later ones, e.g.
+    /// the high over the low priority queue, and take from the later one
+    /// only while the earlier ones have no messages ready.
+    Priority,
+}
 
 /// A [non-consuming] [Consumer] builder.
 ///
@@ -13,17 +93,31 @@ pub struct ConsumerBuilder {
     conn: crate::Connection,
     ex: String,
     queue: String,
+    subs: Vec<(String, Option<String>)>,
+    scheduling: Scheduling,
     kind: lapin::ExchangeKind,
     ex_opts: lapin::options::ExchangeDeclareOptions,
     queue_opts: lapin::options::QueueDeclareOptions,
     bind_opts: lapin::options::QueueBindOptions,
     field_table: lapin::types::FieldTable,
+    queue_field: lapin::types::FieldTable,
+    max_length_bytes: Option<u64>,
+    queue_type: QueueType,
+    prefetch: Option<u16>,
+    routing_key: Option<String>,
+    verify: bool,
     tx_props: lapin::BasicProperties,
     tx_opts: lapin::options::BasicPublishOptions,
     rx_opts: lapin::options::BasicConsumeOptions,
+    rx_field: lapin::types::FieldTable,
+    tag: String,
     ack_opts: lapin::options::BasicAckOptions,
     rej_opts: lapin::options::BasicRejectOptions,
     processor: Box<dyn crate::MessageProcess + Send + Sync>,
+    batcher: Option<Box<dyn crate::BatchProcess + Send + Sync>>,
+    batch_size: usize,
+    batch_timeout: Duration,
+    drop_policy: crate::DropPolicy,
 }
 
 impl ConsumerBuilder {
@@ -32,17 +126,31 @@ impl ConsumerBuilder {
             conn,
             ex: String::from(crate::DEFAULT_EXCHANGE),
             queue: String::from(crate::DEFAULT_QUEUE),
+            subs: Vec::new(),
+            scheduling: Scheduling::default(),
             kind: lapin::ExchangeKind::Direct,
             ex_opts: lapin::options::ExchangeDeclareOptions::default(),
             queue_opts: lapin::options::QueueDeclareOptions::default(),
             bind_opts: lapin::options::QueueBindOptions::default(),
             field_table: lapin::types::FieldTable::default(),
+            queue_field: lapin::types::FieldTable::default(),
+            max_length_bytes: None,
+            queue_type: QueueType::Classic,
+            prefetch: None,
+            routing_key: None,
+            verify: false,
             tx_props: lapin::BasicProperties::default(),
             tx_opts: lapin::options::BasicPublishOptions::default(),
             rx_opts: lapin::options::BasicConsumeOptions::default(),
+            rx_field: lapin::types::FieldTable::default(),
+            tag: String::from(DEFAULT_CONSUMER_TAG),
             ack_opts: lapin::options::BasicAckOptions::default(),
             rej_opts: lapin::options::BasicRejectOptions::default(),
             processor: Box::new(crate::message::EchoProcessor {}),
+            batcher: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            batch_timeout: DEFAULT_BATCH_TIMEOUT,
+            drop_policy: crate::DropPolicy::Leave,
         }
     }
     /// Specify the exchange name.
@@ -55,6 +163,183 @@ impl ConsumerBuilder {
         self.queue = queue.to_string();
         self
     }
+    /// Subscribe to the additional queue on the same channel, declared and
+    /// bound with its name as the routing key the same way as the queue,
+    /// and merge its messages into the [Consumer].
+    ///
+    /// [Consumer]: struct.Consumer.html
+    pub fn subscribe(&mut self, queue: &str) -> &mut Self {
+        self.subs.push((queue.to_string(), None));
+        self
+    }
+    /// Subscribe to the queue bound with the routing key, e.g. the topic
+    /// pattern, the same way as [subscribe].  Bind the same queue, including
+    /// the queue specified by [queue], multiple times to consume the
+    /// messages of multiple routing keys from it.
+    ///
+    /// [subscribe]: #method.subscribe
+    /// [queue]: #method.queue
+    pub fn bind(&mut self, queue: &str, routing_key: &str) -> &mut Self {
+        self.subs
+            .push((queue.to_string(), Some(routing_key.to_string())));
+        self
+    }
+    /// Specify the [Scheduling] among the subscribed queues,
+    /// [Scheduling::RoundRobin] by default.
+    ///
+    /// [Scheduling]: enum.Scheduling.html
+    /// [Scheduling::RoundRobin]: enum.Scheduling.html#variant.RoundRobin
+    pub fn scheduling(&mut self, scheduling: Scheduling) -> &mut Self {
+        self.scheduling = scheduling;
+        self
+    }
+    /// Specify the exchange type, [lapin::ExchangeKind::Direct] by default.
+    ///
+    /// [lapin::ExchangeKind::Direct]: https://docs.rs/lapin/latest/lapin/enum.ExchangeKind.html
+    pub fn exchange_kind(&mut self, kind: lapin::ExchangeKind) -> &mut Self {
+        self.kind = kind;
+        self
+    }
+    /// Specify the binding routing key, which is the queue name by default,
+    /// e.g. the pattern for the topic exchange.
+    pub fn routing_key(&mut self, routing_key: &str) -> &mut Self {
+        self.routing_key = Some(routing_key.to_string());
+        self
+    }
+    /// Verify the exchange and the queue with the passive declarations,
+    /// instead of declaring them, and fail with [Error::NotFound] in case
+    /// either of them is missing.
+    ///
+    /// [Error::NotFound]: ../error/enum.Error.html#variant.NotFound
+    pub fn verify(&mut self, verify: bool) -> &mut Self {
+        self.verify = verify;
+        self
+    }
+    /// Specify the [QueueType], [QueueType::Classic] by default.  The quorum
+    /// and the stream queues are always durable.
+    ///
+    /// [QueueType]: enum.QueueType.html
+    /// [QueueType::Classic]: enum.QueueType.html#variant.Classic
+    pub fn queue_type(&mut self, queue_type: QueueType) -> &mut Self {
+        self.queue_type = queue_type;
+        if queue_type != QueueType::Classic {
+            self.queue_opts.durable = true;
+        }
+        self
+    }
+    /// Specify the queue message TTL through the `x-message-ttl` argument.
+    pub fn message_ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.queue_argument("x-message-ttl", millis(ttl))
+    }
+    /// Specify the queue TTL through the `x-expires` argument, after which
+    /// the unused queue is deleted.
+    pub fn expires(&mut self, ttl: Duration) -> &mut Self {
+        self.queue_argument("x-expires", millis(ttl))
+    }
+    /// Specify the maximum number of messages in the queue through
+    /// the `x-max-length` argument.
+    pub fn max_length(&mut self, len: u32) -> &mut Self {
+        let len = lapin::types::AMQPValue::LongLongInt(i64::from(len));
+        self.queue_argument("x-max-length", len)
+    }
+    /// Specify the maximum total size of the message bodies in the queue
+    /// through the `x-max-length-bytes` argument.  [build] fails with
+    /// [Error::Config] in case it's out of the argument range.
+    ///
+    /// [build]: #method.build
+    /// [Error::Config]: ../error/enum.Error.html#variant.Config
+    pub fn max_length_bytes(&mut self, bytes: u64) -> &mut Self {
+        self.max_length_bytes = Some(bytes);
+        self
+    }
+    /// Specify the maximum message priority of the queue through
+    /// the `x-max-priority` argument.
+    pub fn max_priority(&mut self, priority: u8) -> &mut Self {
+        let priority = lapin::types::AMQPValue::LongLongInt(i64::from(priority));
+        self.queue_argument("x-max-priority", priority)
+    }
+    /// Specify the [Overflow] behavior through the `x-overflow` argument.
+    ///
+    /// [Overflow]: enum.Overflow.html
+    pub fn overflow(&mut self, overflow: Overflow) -> &mut Self {
+        self.queue_argument("x-overflow", overflow.value())
+    }
+    fn queue_argument(&mut self, key: &str, val: lapin::types::AMQPValue) -> &mut Self {
+        self.queue_field.insert(key.into(), val);
+        self
+    }
+    /// Enable the single active consumer on the queue, so that the broker
+    /// delivers to a single consumer at a time and fails over to the next
+    /// one, e.g. to process the messages in order.
+    pub fn single_active_consumer(&mut self, enable: bool) -> &mut Self {
+        let enable = lapin::types::AMQPValue::Boolean(enable);
+        self.queue_argument("x-single-active-consumer", enable)
+    }
+    /// Specify the quorum queue delivery limit, after which the broker
+    /// drops or dead-letters the redelivered message.
+    pub fn delivery_limit(&mut self, limit: u32) -> &mut Self {
+        let limit = lapin::types::AMQPValue::LongLongInt(i64::from(limit));
+        self.queue_argument("x-delivery-limit", limit)
+    }
+    /// Specify the stream queue [StreamOffset], from which the consumer
+    /// starts to consume.
+    ///
+    /// [StreamOffset]: enum.StreamOffset.html
+    pub fn stream_offset(&mut self, offset: StreamOffset) -> &mut Self {
+        use lapin::types::AMQPValue;
+        let val = match offset {
+            StreamOffset::First => AMQPValue::LongString("first".into()),
+            StreamOffset::Last => AMQPValue::LongString("last".into()),
+            StreamOffset::Next => AMQPValue::LongString("next".into()),
+            StreamOffset::Timestamp(secs) => AMQPValue::Timestamp(secs),
+            StreamOffset::Offset(offset) => AMQPValue::LongLongInt(offset as i64),
+        };
+        self.consumer_argument("x-stream-offset", val)
+    }
+    /// Specify the consumer prefetch count.  It's [DEFAULT_STREAM_PREFETCH]
+    /// for the stream queue and unlimited otherwise by default.
+    ///
+    /// [DEFAULT_STREAM_PREFETCH]: constant.DEFAULT_STREAM_PREFETCH.html
+    pub fn prefetch(&mut self, count: u16) -> &mut Self {
+        self.prefetch = Some(count);
+        self
+    }
+    /// Consume in the no-ack mode, e.g. for the fire-and-forget telemetry.
+    /// The broker considers the messages acknowledged once delivered, so
+    /// [Consumer::run] skips the acks and the streamed [Message]s don't
+    /// carry the [Acker] handle.
+    ///
+    /// [Consumer::run]: struct.Consumer.html#method.run
+    /// [Message]: ../message/struct.Message.html
+    /// [Acker]: ../message/struct.Acker.html
+    pub fn no_ack(&mut self, no_ack: bool) -> &mut Self {
+        self.rx_opts.no_ack = no_ack;
+        self
+    }
+    /// Request the exclusive access to the queue, so that no other
+    /// consumer can consume from it.
+    pub fn exclusive(&mut self, exclusive: bool) -> &mut Self {
+        self.rx_opts.exclusive = exclusive;
+        self
+    }
+    /// Specify the consumer tag, [DEFAULT_CONSUMER_TAG] by default.
+    /// The empty tag lets the broker generate the unique one.
+    ///
+    /// [DEFAULT_CONSUMER_TAG]: constant.DEFAULT_CONSUMER_TAG.html
+    pub fn consumer_tag(&mut self, tag: &str) -> &mut Self {
+        self.tag = tag.to_string();
+        self
+    }
+    /// Specify the consumer argument, e.g. `x-priority`.
+    pub fn consumer_argument(&mut self, key: &str, val: lapin::types::AMQPValue) -> &mut Self {
+        self.rx_field.insert(key.into(), val);
+        self
+    }
+    /// Specify the consumer priority through the `x-priority` argument.
+    /// The broker delivers to the higher priority consumers first.
+    pub fn priority(&mut self, priority: i32) -> &mut Self {
+        self.consumer_argument("x-priority", lapin::types::AMQPValue::LongInt(priority))
+    }
     /// Use the provided [MessageProcess] trait object.
     ///
     /// [MessageProcess]: ../message/trait.MessageProcess.html
@@ -65,38 +350,164 @@ impl ConsumerBuilder {
         self.processor = processor;
         self
     }
+    /// Use the provided [BatchProcess] trait object, which switches
+    /// [Consumer::run] to the batch mode.
+    ///
+    /// [BatchProcess]: ../message/trait.BatchProcess.html
+    /// [Consumer::run]: struct.Consumer.html#method.run
+    pub fn with_batch_processor(
+        &mut self,
+        batcher: Box<dyn crate::BatchProcess + Send + Sync>,
+    ) -> &mut Self {
+        self.batcher = Some(batcher);
+        self
+    }
+    /// Specify the maximum number of messages in a batch,
+    /// [DEFAULT_BATCH_SIZE] by default.
+    ///
+    /// [DEFAULT_BATCH_SIZE]: constant.DEFAULT_BATCH_SIZE.html
+    pub fn batch_size(&mut self, size: usize) -> &mut Self {
+        self.batch_size = size;
+        self
+    }
+    /// Specify the maximum time to wait for a batch to fill up after
+    /// the first message, [DEFAULT_BATCH_TIMEOUT] by default.
+    ///
+    /// [DEFAULT_BATCH_TIMEOUT]: constant.DEFAULT_BATCH_TIMEOUT.html
+    pub fn batch_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.batch_timeout = timeout;
+        self
+    }
+    /// Specify the [DropPolicy] of the streamed [Message]s, which is
+    /// [DropPolicy::Leave] by default.
+    ///
+    /// [DropPolicy]: ../message/enum.DropPolicy.html
+    /// [DropPolicy::Leave]: ../message/enum.DropPolicy.html#variant.Leave
+    /// [Message]: ../message/struct.Message.html
+    pub fn drop_policy(&mut self, policy: crate::DropPolicy) -> &mut Self {
+        self.drop_policy = policy;
+        self
+    }
     pub async fn build(&self) -> crate::Result<Consumer> {
+        let mut queue_field = self.queue_field.clone();
+        if let Some(bytes) = self.max_length_bytes {
+            queue_field.insert("x-max-length-bytes".into(), max_length_bytes(bytes)?);
+        }
+        let prefetch = match self.queue_type {
+            QueueType::Classic => self.prefetch,
+            QueueType::Quorum => {
+                queue_field.insert(
+                    "x-queue-type".into(),
+                    lapin::types::AMQPValue::LongString("quorum".into()),
+                );
+                self.prefetch
+            }
+            QueueType::Stream => {
+                if self.rx_opts.no_ack {
+                    return Err(crate::Error::Config(String::from(
+                        "stream queue doesn't support no-ack consumers",
+                    )));
+                }
+                queue_field.insert(
+                    "x-queue-type".into(),
+                    lapin::types::AMQPValue::LongString("stream".into()),
+                );
+                self.prefetch.or(Some(DEFAULT_STREAM_PREFETCH))
+            }
+        };
         let opts = crate::client::QueueOptions {
             kind: self.kind.clone(),
             ex_opts: self.ex_opts.clone(),
             ex_field: self.field_table.clone(),
             queue_opts: self.queue_opts.clone(),
-            queue_field: self.field_table.clone(),
+            queue_field,
             bind_opts: self.bind_opts.clone(),
             bind_field: self.field_table.clone(),
+            routing_key: self.routing_key.clone(),
+            verify: self.verify,
         };
-        let (ch, q) = self.conn.queue(&self.ex, &self.queue, opts).await?;
-        let consume = ch
-            .clone()
-            .basic_consume(
-                q.name().as_str(),
-                "consumer",
-                self.rx_opts.clone(),
-                self.field_table.clone(),
-            )
-            .await
-            .map_err(crate::Error::from)?;
+        let ch = self.conn.channel().await?;
+        let mut queues = vec![(self.queue.clone(), opts.clone())];
+        for (queue, routing_key) in &self.subs {
+            let opts = crate::client::QueueOptions {
+                routing_key: routing_key.clone(),
+                ..opts.clone()
+            };
+            queues.push((queue.clone(), opts));
+        }
+        if let Some(count) = prefetch {
+            ch.basic_qos(count, lapin::options::BasicQosOptions::default())
+                .await
+                .map_err(crate::Error::from)?;
+        }
+        let mut consumers = Vec::with_capacity(queues.len());
+        let mut names: Vec<Arc<str>> = Vec::with_capacity(queues.len());
+        // The server generated name of the ephemeral queue, to bind it
+        // again instead of declaring another one.
+        let mut ephemeral: Option<String> = None;
+        for (queue, opts) in queues {
+            let queue = match &ephemeral {
+                Some(name) if queue == crate::EPHEMERAL_QUEUE => name.clone(),
+                _ => queue,
+            };
+            let q = self.conn.declare_queue(&ch, &self.ex, &queue, opts).await?;
+            if queue == crate::EPHEMERAL_QUEUE {
+                ephemeral = Some(q.name().to_string());
+            }
+            if names.iter().any(|name| name.as_ref() == q.name().as_str()) {
+                // The queue is already consumed, bound with another key.
+                continue;
+            }
+            // The consumer tags should be unique on the channel.
+            let tag = if names.is_empty() || self.tag.is_empty() {
+                self.tag.clone()
+            } else {
+                format!("{}.{}", self.tag, names.len())
+            };
+            let consume = ch
+                .clone()
+                .basic_consume(
+                    q.name().as_str(),
+                    &tag,
+                    self.rx_opts.clone(),
+                    self.rx_field.clone(),
+                )
+                .await
+                .map_err(crate::Error::from)?;
+            consumers.push(Some(consume));
+            names.push(Arc::from(q.name().as_str()));
+        }
         Ok(Consumer {
             ch,
-            consume,
+            timer: self.conn.timer(),
+            consume: Subscriptions {
+                consumers,
+                names,
+                scheduling: self.scheduling,
+                next: 0,
+            },
             ex: self.ex.clone(),
             tx_props: self.tx_props.clone(),
             tx_opts: self.tx_opts.clone(),
+            no_ack: self.rx_opts.no_ack,
             ack_opts: self.ack_opts.clone(),
             rej_opts: self.rej_opts.clone(),
             processor: self.processor.clone(),
+            batcher: self.batcher.clone(),
+            batch_size: self.batch_size,
+            batch_timeout: self.batch_timeout,
+            drop_policy: self.drop_policy,
         })
     }
+    pub(crate) fn queue_name(&self) -> &str {
+        &self.queue
+    }
+    pub(crate) fn exchange_name(&self) -> &str {
+        &self.ex
+    }
+    pub(crate) fn connection(&self) -> &crate::Connection {
+        &self.conn
+    }
 }
 
 /// A zero-cost [lapin::Consumer] abstruction type.
@@ -104,13 +515,19 @@ impl ConsumerBuilder {
 /// [lapin::Consumer]: https://docs.rs/lapin/latest/lapin/struct.Consumer.html
 pub struct Consumer {
     ch: lapin::Channel,
-    consume: lapin::Consumer,
+    timer: Arc<dyn crate::Timer>,
+    consume: Subscriptions,
     ex: String,
     tx_props: lapin::BasicProperties,
     tx_opts: lapin::options::BasicPublishOptions,
+    no_ack: bool,
     ack_opts: lapin::options::BasicAckOptions,
     rej_opts: lapin::options::BasicRejectOptions,
     processor: Box<dyn crate::MessageProcess + Send + Sync>,
+    batcher: Option<Box<dyn crate::BatchProcess + Send + Sync>>,
+    batch_size: usize,
+    batch_timeout: Duration,
+    drop_policy: crate::DropPolicy,
 }
 
 impl Consumer {
@@ -124,13 +541,59 @@ impl Consumer {
         self.processor = processor;
         self
     }
+    /// Use the provided [BatchProcess] trait object.
+    ///
+    /// [BatchProcess]: ../message/trait.BatchProcess.html
+    pub fn with_batch_processor(
+        &mut self,
+        batcher: Box<dyn crate::BatchProcess + Send + Sync>,
+    ) -> &mut Self {
+        self.batcher = Some(batcher);
+        self
+    }
+    /// Starts the [Transaction] over the consumer's channel, to ack the
+    /// streamed messages and to publish their outputs atomically.
+    ///
+    /// The transaction mode is permanent on the channel, so all the acks
+    /// and the replies of the consumer, including the ones of [run], take
+    /// effect only on [Transaction::commit].
+    ///
+    /// [Transaction]: ../transaction/struct.Transaction.html
+    /// [run]: #method.run
+    /// [Transaction::commit]: ../transaction/struct.Transaction.html#method.commit
+    pub async fn transaction(&self) -> crate::Result<crate::Transaction> {
+        crate::Transaction::select(self.ch.clone()).await
+    }
+    /// Closes the consumer's channel, so that the broker requeues the
+    /// unacknowledged messages.
+    pub async fn close(&self) -> crate::Result<()> {
+        self.ch
+            .close(lapin::protocol::constants::REPLY_SUCCESS as u16, "OK")
+            .await
+            .map_err(crate::Error::from)
+    }
+    /// Processes the messages until the consumer is cancelled.  The message
+    /// is acked, after sending the reply to the `reply_to` queue, if any,
+    /// on success, nacked with the requeue in case of
+    /// [MessageError::Requeue] and rejected without the requeue otherwise.
+    ///
+    /// [MessageError::Requeue]: ../message/enum.MessageError.html#variant.Requeue
     pub async fn run(&mut self) -> crate::Result<()> {
+        if self.batcher.is_some() {
+            return self.run_batch().await;
+        }
         while let Some(msg) = self.consume.next().await {
             match msg {
                 Ok(msg) => {
-                    let req = &crate::Message::new(msg);
-                    match self.processor.process(req).await {
+                    let req = &msg;
+                    let in_flight = crate::metrics::processing(req);
+                    let start = Instant::now();
+                    let result = self.processor.process(req).await;
+                    crate::metrics::process_duration(req, start.elapsed());
+                    drop(in_flight);
+                    match result {
                         Ok(resp) => self.response(req, &resp).await?,
+                        Err(err) if requeue(&err) => self.nack(req).await?,
                         Err(_err) => self.reject(req).await?,
                     }
                 }
@@ -139,31 +602,142 @@ impl Consumer {
         }
         Ok(())
     }
+    /// Accumulates up to the batch size messages, or until the batch
+    /// timeout after the first message, and hands them to [BatchProcess]
+    /// at once.  The whole batch is acked on success, or nacked in case of
+    /// the error, with the requeue in case of [MessageError::Nack] or
+    /// [MessageError::Requeue].  It's
+    /// settled with a single multiple-ack or nack only for the contiguous
+    /// delivery tags of the single subscription, and per message otherwise.
+    /// [MessageError::Drop] acks the batch silently.  The batch processing
+    /// duration is recorded with the labels of its first message.
+    ///
+    /// [BatchProcess]: ../message/trait.BatchProcess.html
+    /// [MessageError::Nack]: ../message/enum.MessageError.html#variant.Nack
+    /// [MessageError::Requeue]: ../message/enum.MessageError.html#variant.Requeue
+    /// [MessageError::Drop]: ../message/enum.MessageError.html#variant.Drop
+    async fn run_batch(&mut self) -> crate::Result<()> {
+        loop {
+            let mut batch = match self.consume.next().await {
+                Some(Ok(msg)) => vec![msg],
+                Some(Err(err)) => return Err(crate::Error::from(err)),
+                None => return Ok(()),
+            };
+            let mut timeout = self.timer.delay(self.batch_timeout);
+            while batch.len() < self.batch_size {
+                match future::select(self.consume.next(), &mut timeout).await {
+                    Either::Left((Some(Ok(msg)), _)) => batch.push(msg),
+                    Either::Left((Some(Err(err)), _)) => return Err(crate::Error::from(err)),
+                    Either::Left((None, _)) | Either::Right(_) => break,
+                }
+            }
+            let (tags, multiple) = self.consume.batch_tags(&batch);
+            let in_flight: Vec<_> = batch.iter().map(crate::metrics::processing).collect();
+            let start = Instant::now();
+            let result = match self.batcher.as_mut() {
+                Some(batcher) => batcher.process(&batch).await,
+                None => Ok(()),
+            };
+            crate::metrics::process_duration(&batch[0], start.elapsed());
+            drop(in_flight);
+            if self.no_ack {
+                continue;
+            }
+            let requeue = match result {
+                Ok(()) | Err(crate::MessageError::Drop) => None,
+                Err(crate::MessageError::Reject) => Some(false),
+                Err(crate::MessageError::Nack) | Err(crate::MessageError::Requeue) => Some(true),
+            };
+            for tag in tags {
+                match requeue {
+                    None => {
+                        let opts = lapin::options::BasicAckOptions { multiple };
+                        self.ch.basic_ack(tag, opts).await
+                    }
+                    Some(requeue) => {
+                        let opts = lapin::options::BasicNackOptions { multiple, requeue };
+                        self.ch.basic_nack(tag, opts).await
+                    }
+                }
+                .map_err(crate::Error::from)?;
+            }
+            for msg in &batch {
+                let queue = msg.queue().unwrap_or_default();
+                match requeue {
+                    None => crate::metrics::acked(msg.exchange(), queue),
+                    Some(false) => crate::metrics::rejected(msg.exchange(), queue),
+                    Some(true) => crate::metrics::nacked(msg.exchange(), queue),
+                }
+            }
+        }
+    }
     pub async fn response(&mut self, req: &crate::Message, resp: &[u8]) -> crate::Result<()> {
+        if let Some(acker) = req.acker() {
+            acker.settle()?;
+        }
         if let Some(reply_to) = req.reply_to() {
-            self.send(reply_to, resp).await?;
+            let props = match req.correlation_id() {
+                Some(id) => self.tx_props.clone().with_correlation_id(id.into()),
+                None => self.tx_props.clone(),
+            };
+            self.send(reply_to, resp, props).await?;
+        }
+        if self.no_ack {
+            return Ok(());
         }
         self.ch
             .basic_ack(req.delivery_tag(), self.ack_opts.clone())
             .await
             .map_err(crate::Error::from)?;
+        crate::metrics::acked(req.exchange(), req.queue().unwrap_or_default());
         Ok(())
     }
     pub async fn reject(&mut self, req: &crate::Message) -> crate::Result<()> {
+        if let Some(acker) = req.acker() {
+            acker.settle()?;
+        }
+        if self.no_ack {
+            return Ok(());
+        }
         self.ch
             .basic_reject(req.delivery_tag(), self.rej_opts.clone())
             .await
             .map_err(crate::Error::from)?;
+        crate::metrics::rejected(req.exchange(), req.queue().unwrap_or_default());
         Ok(())
     }
-    async fn send(&mut self, routing_key: &str, msg: &[u8]) -> crate::Result<()> {
+    /// Nacks the message to be requeued.
+    pub async fn nack(&mut self, req: &crate::Message) -> crate::Result<()> {
+        if let Some(acker) = req.acker() {
+            acker.settle()?;
+        }
+        if self.no_ack {
+            return Ok(());
+        }
+        let opts = lapin::options::BasicNackOptions {
+            requeue: true,
+            ..lapin::options::BasicNackOptions::default()
+        };
+        self.ch
+            .basic_nack(req.delivery_tag(), opts)
+            .await
+            .map_err(crate::Error::from)?;
+        crate::metrics::nacked(req.exchange(), req.queue().unwrap_or_default());
+        Ok(())
+    }
+    async fn send(
+        &mut self,
+        routing_key: &str,
+        msg: &[u8],
+        props: lapin::BasicProperties,
+    ) -> crate::Result<()> {
         self.ch
             .basic_publish(
                 &self.ex,
                 &routing_key,
                 self.tx_opts.clone(),
                 msg.to_vec(),
-                self.tx_props.clone(),
+                props,
             )
             .await
             .map_err(crate::Error::from)?;
@@ -171,16 +745,320 @@ impl Consumer {
     }
 }
 
+/// Returns `true` in case [Consumer::run] requeues the message failed with
+/// the error, instead of rejecting it.
+///
+/// [Consumer::run]: struct.Consumer.html#method.run
+fn requeue(err: &crate::MessageError) -> bool {
+    matches!(err, crate::MessageError::Requeue)
+}
+
+/// Converts the duration into the milliseconds argument value.
+pub(crate) fn millis(duration: Duration) -> lapin::types::AMQPValue {
+    lapin::types::AMQPValue::LongLongInt(duration.as_millis() as i64)
+}
+
+/// Converts the bytes into the `x-max-length-bytes` argument value, or
+/// fails with [Error::Config] in case it's out of range.
+///
+/// [Error::Config]: ../error/enum.Error.html#variant.Config
+pub(crate) fn max_length_bytes(bytes: u64) -> crate::Result<lapin::types::AMQPValue> {
+    use std::convert::TryFrom;
+    match i64::try_from(bytes) {
+        Ok(bytes) => Ok(lapin::types::AMQPValue::LongLongInt(bytes)),
+        Err(_) => Err(crate::Error::Config(format!(
+            "x-max-length-bytes {} is out of range",
+            bytes
+        ))),
+    }
+}
+
+/// The subscribed [lapin::Consumer]s, merged into a single stream
+/// through the [Scheduling].
+///
+/// [lapin::Consumer]: https://docs.rs/lapin/latest/lapin/struct.Consumer.html
+/// [Scheduling]: enum.Scheduling.html
+struct Subscriptions<C = lapin::Consumer> {
+    consumers: Vec<Option<C>>,
+    names: Vec<Arc<str>>,
+    scheduling: Scheduling,
+    next: usize,
+}
+
+impl<C> Subscriptions<C> {
+    /// Returns the delivery tags to settle the batch with, and whether
+    /// to settle them with the multiple flag.  The multiple flag settles
+    /// all the deliveries up to the tag on the channel, which are out of
+    /// order across the subscriptions, so that it's used only for
+    /// the contiguous tags of the single subscription.
+    fn batch_tags(&self, batch: &[crate::Message]) -> (Vec<u64>, bool) {
+        let contiguous = batch
+            .windows(2)
+            .all(|pair| pair[1].delivery_tag() == pair[0].delivery_tag() + 1);
+        if self.consumers.len() == 1 && contiguous {
+            (vec![batch[batch.len() - 1].delivery_tag()], true)
+        } else {
+            (
+                batch.iter().map(crate::Message::delivery_tag).collect(),
+                false,
+            )
+        }
+    }
+}
+
+impl<C> Stream for Subscriptions<C>
+where
+    C: Stream<Item = lapin::Result<lapin::message::Delivery>> + Unpin,
+{
+    type Item = lapin::Result<crate::Message>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let len = self.consumers.len();
+        let start = match self.scheduling {
+            Scheduling::RoundRobin => self.next,
+            Scheduling::Priority => 0,
+        };
+        let mut done = true;
+        for i in (start..len).chain(0..start) {
+            let c = match self.consumers[i].as_mut() {
+                Some(c) => c,
+                None => continue,
+            };
+            match Pin::new(c).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    self.next = (i + 1) % len;
+                    let queue = self.names[i].clone();
+                    let item = item.map(|msg| crate::Message::with_queue(msg, queue));
+                    if let Ok(msg) = &item {
+                        crate::metrics::consumed(msg);
+                    }
+                    return Poll::Ready(Some(item));
+                }
+                Poll::Ready(None) => self.consumers[i] = None,
+                Poll::Pending => done = false,
+            }
+        }
+        if done {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
 impl Stream for Consumer {
     type Item = Result<crate::Message, crate::Error>;
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let c = &mut self.consume;
         let c = Pin::new(c);
         match c.poll_next(cx) {
-            Poll::Ready(Some(Ok(msg))) => Poll::Ready(Some(Ok(crate::Message::new(msg)))),
+            Poll::Ready(Some(Ok(msg))) if self.no_ack => Poll::Ready(Some(Ok(msg))),
+            Poll::Ready(Some(Ok(msg))) => {
+                let acker = crate::Acker::new(
+                    self.ch.clone(),
+                    &msg,
+                    &self.ex,
+                    self.tx_props.clone(),
+                    self.tx_opts.clone(),
+                    self.drop_policy,
+                );
+                Poll::Ready(Some(Ok(msg.with_acker(acker))))
+            }
             Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err.into()))),
             Poll::Ready(None) => Poll::Ready(None),
             Poll::Pending => Poll::Pending,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn subscriptions() {
+        use super::Scheduling;
+        use futures::executor::block_on_stream;
+        use futures::stream;
+        fn delivery() -> lapin::message::Delivery {
+            lapin::message::Delivery {
+                delivery_tag: 1,
+                exchange: "".into(),
+                routing_key: "".into(),
+                redelivered: false,
+                properties: lapin::BasicProperties::default(),
+                data: Vec::new(),
+            }
+        }
+        struct Test {
+            data: (Scheduling, Vec<usize>),
+            want: Vec<&'static str>,
+        }
+        let tests = [
+            Test {
+                data: (Scheduling::RoundRobin, vec![2, 1, 2]),
+                want: vec!["a", "b", "c", "a", "c"],
+            },
+            Test {
+                data: (Scheduling::RoundRobin, vec![0, 3]),
+                want: vec!["b", "b", "b"],
+            },
+            Test {
+                data: (Scheduling::Priority, vec![2, 1, 2]),
+                want: vec!["a", "a", "b", "c", "c"],
+            },
+            Test {
+                data: (Scheduling::Priority, vec![0, 1, 1]),
+                want: vec!["b", "c"],
+            },
+        ];
+        for t in &tests {
+            let (scheduling, counts) = &t.data;
+            let names = ["a", "b", "c"];
+            let consumers = counts
+                .iter()
+                .map(|count| {
+                    let deliveries: Vec<lapin::Result<_>> =
+                        (0..*count).map(|_| delivery()).map(Ok).collect();
+                    Some(stream::iter(deliveries))
+                })
+                .collect();
+            let subs = super::Subscriptions {
+                consumers,
+                names: names[..counts.len()]
+                    .iter()
+                    .map(|&name| name.into())
+                    .collect(),
+                scheduling: *scheduling,
+                next: 0,
+            };
+            let got: Vec<_> = block_on_stream(subs)
+                .map(|msg| msg.unwrap().queue().unwrap().to_string())
+                .collect();
+            assert_eq!(t.want, got, "{:?}", t.data);
+        }
+    }
+    #[test]
+    fn batch_tags() {
+        use super::Scheduling;
+        use futures::executor::block_on_stream;
+        use futures::stream;
+        fn delivery(tag: u64) -> lapin::message::Delivery {
+            lapin::message::Delivery {
+                delivery_tag: tag,
+                exchange: "".into(),
+                routing_key: "".into(),
+                redelivered: false,
+                properties: lapin::BasicProperties::default(),
+                data: Vec::new(),
+            }
+        }
+        struct Test {
+            data: (Scheduling, Vec<Vec<u64>>),
+            want: (Vec<u64>, bool),
+        }
+        let tests = [
+            Test {
+                data: (Scheduling::RoundRobin, vec![vec![1, 2, 3]]),
+                want: (vec![3], true),
+            },
+            Test {
+                data: (Scheduling::RoundRobin, vec![vec![1, 3, 4]]),
+                want: (vec![1, 3, 4], false),
+            },
+            // The interleaved tags of the two queues on the same channel.
+            Test {
+                data: (Scheduling::RoundRobin, vec![vec![1, 3, 5], vec![2, 4]]),
+                want: (vec![1, 2, 3, 4, 5], false),
+            },
+            Test {
+                data: (Scheduling::Priority, vec![vec![1, 3, 5], vec![2, 4]]),
+                want: (vec![1, 3, 5, 2, 4], false),
+            },
+        ];
+        for t in &tests {
+            let (scheduling, tags) = &t.data;
+            let consumers: Vec<_> = tags
+                .iter()
+                .map(|tags| {
+                    let deliveries: Vec<lapin::Result<_>> =
+                        tags.iter().map(|&tag| delivery(tag)).map(Ok).collect();
+                    Some(stream::iter(deliveries))
+                })
+                .collect();
+            let names = consumers.iter().map(|_| "q".into()).collect();
+            let mut subs = super::Subscriptions {
+                consumers,
+                names,
+                scheduling: *scheduling,
+                next: 0,
+            };
+            let batch: Vec<_> = block_on_stream(&mut subs).map(Result::unwrap).collect();
+            assert_eq!(t.want, subs.batch_tags(&batch), "{:?}", t.data);
+        }
+    }
+    #[test]
+    fn requeue() {
+        use crate::MessageError;
+        struct Test {
+            data: MessageError,
+            want: bool,
+        }
+        let tests = [
+            Test {
+                data: MessageError::Drop,
+                want: false,
+            },
+            Test {
+                data: MessageError::Reject,
+                want: false,
+            },
+            // The poison message shouldn't be redelivered forever.
+            Test {
+                data: MessageError::Nack,
+                want: false,
+            },
+            Test {
+                data: MessageError::Requeue,
+                want: true,
+            },
+        ];
+        for t in &tests {
+            assert_eq!(t.want, super::requeue(&t.data));
+        }
+    }
+    #[test]
+    fn max_length_bytes() {
+        use lapin::types::AMQPValue;
+        struct Test {
+            data: u64,
+            want: crate::Result<AMQPValue>,
+        }
+        let tests = [
+            Test {
+                data: 0,
+                want: Ok(AMQPValue::LongLongInt(0)),
+            },
+            Test {
+                data: 1 << 30,
+                want: Ok(AMQPValue::LongLongInt(1 << 30)),
+            },
+            Test {
+                data: i64::MAX as u64,
+                want: Ok(AMQPValue::LongLongInt(i64::MAX)),
+            },
+            Test {
+                data: i64::MAX as u64 + 1,
+                want: Err(crate::Error::Config(String::from(
+                    "x-max-length-bytes 9223372036854775808 is out of range",
+                ))),
+            },
+            Test {
+                data: u64::MAX,
+                want: Err(crate::Error::Config(String::from(
+                    "x-max-length-bytes 18446744073709551615 is out of range",
+                ))),
+            },
+        ];
+        for t in &tests {
+            assert_eq!(t.want, super::max_length_bytes(t.data), "{}", t.data);
+        }
+    }
+}
@@ -0,0 +1,387 @@
+// SPDX-License-Identifier: Apache-2.0 AND MIT
+//! `DedupProcessor` struct and `DedupStore` trait
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// The default maximum number of keys in [LruDedupStore].
+///
+/// [LruDedupStore]: struct.LruDedupStore.html
+pub const DEFAULT_DEDUP_CAPACITY: usize = 10_000;
+/// The default [SqliteDedupStore] table name.
+///
+/// [SqliteDedupStore]: struct.SqliteDedupStore.html
+#[cfg(feature = "sqlite")]
+pub const DEFAULT_DEDUP_TABLE: &str = "async_mq_dedup";
+
+/// A trait to record the keys of the processed messages, with their
+/// replies, for [DedupProcessor].  The clones share the same records.
+///
+/// [DedupProcessor]: struct.DedupProcessor.html
+#[async_trait]
+pub trait DedupStore {
+    /// Async method to get the reply of the already processed message.
+    async fn get(&mut self, key: &str) -> crate::Result<Option<Vec<u8>>>;
+    /// Async method to record the processed message with its reply.
+    async fn put(&mut self, key: &str, reply: &[u8]) -> crate::Result<()>;
+    fn boxed_clone(&self) -> Box<dyn DedupStore + Send + Sync>;
+}
+
+// https://users.rust-lang.org/t/solved-is-it-possible-to-clone-a-boxed-trait-object/1714/6
+impl Clone for Box<dyn DedupStore + Send + Sync> {
+    fn clone(&self) -> Box<dyn DedupStore + Send + Sync> {
+        self.boxed_clone()
+    }
+}
+
+/// A deduplication key extractor, which returns `None` for the message
+/// to be processed without the deduplication.
+pub type DedupKey = Arc<dyn Fn(&crate::Message) -> Option<String> + Send + Sync>;
+
+/// A [MessageProcess] wrapper, which processes the message only once per
+/// key, the message ID by default, and returns the recorded reply for
+/// the duplicates, e.g. the redeliveries after the consumer crash or
+/// the retried RPC requests.
+///
+/// The concurrent duplicates, e.g. delivered to two consumers at once,
+/// are not guarded against.  In case the [DedupStore] fails, the message
+/// is nacked to be requeued and processed again, i.e. at least once.
+///
+/// [MessageProcess]: ../message/trait.MessageProcess.html
+/// [DedupStore]: trait.DedupStore.html
+#[derive(Clone)]
+pub struct DedupProcessor {
+    processor: Box<dyn crate::MessageProcess + Send + Sync>,
+    store: Box<dyn DedupStore + Send + Sync>,
+    key: DedupKey,
+}
+
+impl DedupProcessor {
+    pub fn new(
+        processor: Box<dyn crate::MessageProcess + Send + Sync>,
+        store: Box<dyn DedupStore + Send + Sync>,
+    ) -> Self {
+        Self {
+            processor,
+            store,
+            key: Arc::new(|msg| msg.message_id().map(String::from)),
+        }
+    }
+    /// Use the provided [DedupKey] extractor, instead of the message ID.
+    ///
+    /// [DedupKey]: type.DedupKey.html
+    pub fn with_key(&mut self, key: DedupKey) -> &mut Self {
+        self.key = key;
+        self
+    }
+}
+
+#[async_trait]
+impl crate::MessageProcess for DedupProcessor {
+    /// Processes the message, unless its key is already recorded, and
+    /// records the reply.  It returns [MessageError::Requeue] in case the
+    /// [DedupStore] lookup or the record fails.
+    ///
+    /// [MessageError::Requeue]: ../message/enum.MessageError.html#variant.Requeue
+    /// [DedupStore]: trait.DedupStore.html
+    async fn process(&mut self, msg: &crate::Message) -> Result<Vec<u8>, crate::MessageError> {
+        let key = match (self.key)(msg) {
+            Some(key) => key,
+            None => return self.processor.process(msg).await,
+        };
+        match self.store.get(&key).await {
+            Ok(Some(reply)) => return Ok(reply),
+            Ok(None) => {}
+            Err(_) => return Err(crate::MessageError::Requeue),
+        }
+        let reply = self.processor.process(msg).await?;
+        // Processes the requeued message again, as it's not recorded.
+        if self.store.put(&key, &reply).await.is_err() {
+            return Err(crate::MessageError::Requeue);
+        }
+        Ok(reply)
+    }
+    fn boxed_clone(&self) -> Box<dyn crate::MessageProcess + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+/// An in-memory [DedupStore], which evicts the least recently used keys
+/// beyond the capacity.
+///
+/// [DedupStore]: trait.DedupStore.html
+#[derive(Clone)]
+pub struct LruDedupStore(Arc<Mutex<Lru>>);
+
+struct Lru {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<String, (u64, Vec<u8>)>,
+    order: BTreeMap<u64, String>,
+}
+
+impl Default for LruDedupStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEDUP_CAPACITY)
+    }
+}
+
+impl LruDedupStore {
+    pub fn new(capacity: usize) -> Self {
+        Self(Arc::new(Mutex::new(Lru {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        })))
+    }
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Lru {
+    fn get(&mut self, key: &str) -> Option<Vec<u8>> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (used, reply) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        self.order.insert(tick, key.to_string());
+        *used = tick;
+        Some(reply.clone())
+    }
+    fn put(&mut self, key: &str, reply: &[u8]) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some((used, _)) = self.entries.insert(key.to_string(), (tick, reply.to_vec())) {
+            self.order.remove(&used);
+        }
+        self.order.insert(tick, key.to_string());
+        while self.entries.len() > self.capacity {
+            let oldest = match self.order.keys().next() {
+                Some(oldest) => *oldest,
+                None => break,
+            };
+            if let Some(key) = self.order.remove(&oldest) {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl DedupStore for LruDedupStore {
+    async fn get(&mut self, key: &str) -> crate::Result<Option<Vec<u8>>> {
+        Ok(self.0.lock().unwrap().get(key))
+    }
+    async fn put(&mut self, key: &str, reply: &[u8]) -> crate::Result<()> {
+        self.0.lock().unwrap().put(key, reply);
+        Ok(())
+    }
+    fn boxed_clone(&self) -> Box<dyn DedupStore + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+/// A [DedupStore] over the SQLite table, which survives the restarts and
+/// is shared by the consumers over the same database file.  It's provided
+/// with the `sqlite` feature, which the `outbox` feature also enables.
+///
+/// The queries block the executor thread, while holding the connection
+/// lock, so keep the database local, e.g. a file on the local disk, or
+/// run the consumers on a thread pool, e.g. [ThreadPoolExecutor].
+///
+/// [DedupStore]: trait.DedupStore.html
+/// [ThreadPoolExecutor]: ../runtime/struct.ThreadPoolExecutor.html
+#[cfg(feature = "sqlite")]
+#[derive(Clone)]
+pub struct SqliteDedupStore {
+    db: Arc<Mutex<rusqlite::Connection>>,
+    table: String,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteDedupStore {
+    /// Creates the store over the [DEFAULT_DEDUP_TABLE], and the table
+    /// itself unless it exists.
+    ///
+    /// [DEFAULT_DEDUP_TABLE]: constant.DEFAULT_DEDUP_TABLE.html
+    pub fn new(db: rusqlite::Connection) -> crate::Result<Self> {
+        Self::with_table(db, DEFAULT_DEDUP_TABLE)
+    }
+    /// Creates the store over the specified table, which name consists of
+    /// the ASCII alphanumerics and the underscores.
+    pub fn with_table(db: rusqlite::Connection, table: &str) -> crate::Result<Self> {
+        crate::sqlite::check_table("dedup", table)?;
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                key TEXT PRIMARY KEY,
+                reply BLOB NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+            table
+        );
+        db.execute(&sql, rusqlite::NO_PARAMS)?;
+        Ok(Self {
+            db: Arc::new(Mutex::new(db)),
+            table: String::from(table),
+        })
+    }
+    /// Deletes the keys recorded before the specified time and returns
+    /// the number of them.
+    pub fn purge(&self, before: std::time::SystemTime) -> crate::Result<usize> {
+        let sql = format!("DELETE FROM {} WHERE created_at < ?1", self.table);
+        let db = self.db.lock().unwrap();
+        Ok(db.execute(&sql, rusqlite::params![crate::sqlite::unix_millis(before)])?)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl DedupStore for SqliteDedupStore {
+    async fn get(&mut self, key: &str) -> crate::Result<Option<Vec<u8>>> {
+        use rusqlite::OptionalExtension;
+        let sql = format!("SELECT reply FROM {} WHERE key = ?1", self.table);
+        let db = self.db.lock().unwrap();
+        let reply = db
+            .query_row(&sql, rusqlite::params![key], |row| row.get(0))
+            .optional()?;
+        Ok(reply)
+    }
+    async fn put(&mut self, key: &str, reply: &[u8]) -> crate::Result<()> {
+        let sql = format!(
+            "INSERT OR REPLACE INTO {} (key, reply, created_at) VALUES (?1, ?2, ?3)",
+            self.table
+        );
+        let now = crate::sqlite::unix_millis(std::time::SystemTime::now());
+        let db = self.db.lock().unwrap();
+        db.execute(&sql, rusqlite::params![key, reply, now])?;
+        Ok(())
+    }
+    fn boxed_clone(&self) -> Box<dyn DedupStore + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DedupStore;
+    use crate::MessageProcess;
+    use async_trait::async_trait;
+    use futures::executor::block_on;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Clone)]
+    struct CountProcessor(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl MessageProcess for CountProcessor {
+        async fn process(&mut self, msg: &crate::Message) -> Result<Vec<u8>, crate::MessageError> {
+            let count = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(format!("{}:{}", String::from_utf8_lossy(msg.data()), count).into_bytes())
+        }
+        fn boxed_clone(&self) -> Box<dyn MessageProcess + Send + Sync> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn message(id: Option<&str>, data: &str) -> crate::Message {
+        let mut props = lapin::BasicProperties::default();
+        if let Some(id) = id {
+            props = props.with_message_id(id.into());
+        }
+        crate::Message::new(lapin::message::Delivery {
+            delivery_tag: 1,
+            exchange: "".into(),
+            routing_key: "".into(),
+            redelivered: false,
+            properties: props,
+            data: data.as_bytes().to_vec(),
+        })
+    }
+
+    #[test]
+    fn lru_evict() {
+        struct Test {
+            data: Vec<&'static str>,
+            want: Vec<(&'static str, bool)>,
+        }
+        let tests = [
+            Test {
+                data: vec!["a", "b"],
+                want: vec![("a", true), ("b", true)],
+            },
+            Test {
+                data: vec!["a", "b", "c"],
+                want: vec![("a", false), ("b", true), ("c", true)],
+            },
+            Test {
+                data: vec!["a", "b", "a", "c"],
+                want: vec![("a", true), ("b", false), ("c", true)],
+            },
+        ];
+        for t in &tests {
+            let mut store = super::LruDedupStore::new(2);
+            for key in &t.data {
+                if block_on(store.get(key)).unwrap().is_none() {
+                    block_on(store.put(key, key.as_bytes())).unwrap();
+                }
+            }
+            for (key, want) in &t.want {
+                let got = store.0.lock().unwrap().entries.contains_key(*key);
+                assert_eq!(*want, got, "{:?}: {}", t.data, key);
+            }
+        }
+    }
+    #[test]
+    fn process() {
+        struct Test {
+            data: Vec<(Option<&'static str>, &'static str)>,
+            want: Vec<&'static str>,
+        }
+        let tests = [
+            Test {
+                data: vec![(Some("1"), "a"), (Some("2"), "b")],
+                want: vec!["a:1", "b:2"],
+            },
+            Test {
+                data: vec![(Some("1"), "a"), (Some("1"), "a"), (Some("2"), "b")],
+                want: vec!["a:1", "a:1", "b:2"],
+            },
+            Test {
+                data: vec![(None, "a"), (None, "a")],
+                want: vec!["a:1", "a:2"],
+            },
+        ];
+        for t in &tests {
+            let count = Arc::new(AtomicUsize::new(0));
+            let mut processor = super::DedupProcessor::new(
+                Box::new(CountProcessor(count)),
+                Box::new(super::LruDedupStore::default()),
+            );
+            let mut got = Vec::new();
+            for (id, data) in &t.data {
+                let reply = block_on(processor.process(&message(*id, data)));
+                got.push(String::from_utf8(reply.ok().unwrap()).unwrap());
+            }
+            assert_eq!(t.want, got);
+        }
+    }
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_store() {
+        let db = rusqlite::Connection::open_in_memory().unwrap();
+        let mut store = super::SqliteDedupStore::new(db).unwrap();
+        assert_eq!(None, block_on(store.get("1")).unwrap());
+        block_on(store.put("1", b"reply")).unwrap();
+        assert_eq!(Some(b"reply".to_vec()), block_on(store.get("1")).unwrap());
+        assert_eq!(0, store.purge(std::time::UNIX_EPOCH).unwrap());
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(1);
+        assert_eq!(1, store.purge(later).unwrap());
+        assert_eq!(None, block_on(store.get("1")).unwrap());
+    }
+}
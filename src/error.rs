@@ -6,7 +6,41 @@ pub enum Error {
     /// [lapin::Error] variant.
     ///
     /// [lapin::Error]: https://docs.rs/lapin/latest/lapin/enum.Error.html
-    Internal(lapin::Error),
+    Internal(Box<lapin::Error>),
+    /// TLS error variant, with the [native_tls::Error] message.
+    ///
+    /// [native_tls::Error]: https://docs.rs/native-tls/latest/native_tls/struct.Error.html
+    Tls(String),
+    /// Invalid configuration variant, e.g. a malformed AMQP URI.
+    Config(String),
+    /// Blocked connection variant, returned when the broker blocks
+    /// the connection, e.g. due to the memory or the disk alarm.
+    Blocked,
+    /// Missing entity variant, returned by the passive declaration,
+    /// e.g. `queue 'orders'`.
+    NotFound(String),
+    /// Rejected publish variant, returned when the broker returns
+    /// the confirmed message, e.g. the unroutable `mandatory` one.
+    Rejected,
+    /// Unconfirmed publish variant, returned when the publisher confirm
+    /// doesn't arrive in time.  lapin doesn't resolve the confirmation of
+    /// the message nacked by the broker, e.g. due to the `reject-publish`
+    /// queue overflow, so that the nack surfaces as this variant.
+    Unconfirmed,
+    /// Timeout variant, returned when the RPC reply doesn't arrive in time.
+    Timeout,
+    /// Open circuit variant, returned by the circuit breaker without
+    /// calling the failing service.
+    CircuitOpen,
+    /// Rate limited variant, returned by the fail-fast rate limiter.
+    RateLimited,
+    /// Storage error variant, e.g. of the outbox database.
+    Store(String),
+    /// Missing executor variant, returned when the background task is
+    /// spawned without the executor provided by [Client::task_executor].
+    ///
+    /// [Client::task_executor]: ../client/struct.Client.html#method.task_executor
+    NoExecutor,
     /// Other error variant.
     Other,
 }
@@ -14,7 +48,18 @@ pub enum Error {
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            Self::Internal(err) => Some(err),
+            Self::Internal(err) => Some(err.as_ref()),
+            Self::Tls(_) => None,
+            Self::Config(_) => None,
+            Self::Blocked => None,
+            Self::NotFound(_) => None,
+            Self::Rejected => None,
+            Self::Unconfirmed => None,
+            Self::Timeout => None,
+            Self::CircuitOpen => None,
+            Self::RateLimited => None,
+            Self::Store(_) => None,
+            Self::NoExecutor => None,
             Self::Other => None,
         }
     }
@@ -24,6 +69,17 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Internal(err) => err.fmt(f),
+            Self::Tls(msg) => write!(f, "tls error: {}", msg),
+            Self::Config(msg) => write!(f, "invalid configuration: {}", msg),
+            Self::Blocked => write!(f, "connection blocked"),
+            Self::NotFound(entity) => write!(f, "{} not found", entity),
+            Self::Rejected => write!(f, "publish rejected"),
+            Self::Unconfirmed => write!(f, "publish unconfirmed"),
+            Self::Timeout => write!(f, "timed out"),
+            Self::CircuitOpen => write!(f, "circuit open"),
+            Self::RateLimited => write!(f, "rate limited"),
+            Self::Store(msg) => write!(f, "store error: {}", msg),
+            Self::NoExecutor => write!(f, "no task executor"),
             Self::Other => write!(f, "other error"),
         }
     }
@@ -33,6 +89,17 @@ impl std::fmt::Debug for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Internal(err) => err.fmt(f),
+            Self::Tls(msg) => write!(f, "Error::Tls({:?})", msg),
+            Self::Config(msg) => write!(f, "Error::Config({:?})", msg),
+            Self::Blocked => write!(f, "Error::Blocked"),
+            Self::NotFound(entity) => write!(f, "Error::NotFound({:?})", entity),
+            Self::Rejected => write!(f, "Error::Rejected"),
+            Self::Unconfirmed => write!(f, "Error::Unconfirmed"),
+            Self::Timeout => write!(f, "Error::Timeout"),
+            Self::CircuitOpen => write!(f, "Error::CircuitOpen"),
+            Self::RateLimited => write!(f, "Error::RateLimited"),
+            Self::Store(msg) => write!(f, "Error::Store({:?})", msg),
+            Self::NoExecutor => write!(f, "Error::NoExecutor"),
             Self::Other => write!(f, "Error::Other"),
         }
     }
@@ -40,7 +107,20 @@ impl std::fmt::Debug for Error {
 
 impl From<lapin::Error> for Error {
     fn from(err: lapin::Error) -> Self {
-        Self::Internal(err)
+        Self::Internal(Box::new(err))
+    }
+}
+
+impl From<native_tls::Error> for Error {
+    fn from(err: native_tls::Error) -> Self {
+        Self::Tls(err.to_string())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::Store(err.to_string())
     }
 }
 
@@ -51,6 +131,29 @@ impl std::cmp::PartialEq for Error {
                 Self::Internal(other) => Self::eq_internal(err, other),
                 _ => false,
             },
+            Self::Tls(err) => match other {
+                Self::Tls(other) => err == other,
+                _ => false,
+            },
+            Self::Config(msg) => match other {
+                Self::Config(other) => msg == other,
+                _ => false,
+            },
+            Self::Blocked => matches!(other, Self::Blocked),
+            Self::NotFound(entity) => match other {
+                Self::NotFound(other) => entity == other,
+                _ => false,
+            },
+            Self::Rejected => matches!(other, Self::Rejected),
+            Self::Unconfirmed => matches!(other, Self::Unconfirmed),
+            Self::Timeout => matches!(other, Self::Timeout),
+            Self::CircuitOpen => matches!(other, Self::CircuitOpen),
+            Self::RateLimited => matches!(other, Self::RateLimited),
+            Self::Store(msg) => match other {
+                Self::Store(other) => msg == other,
+                _ => false,
+            },
+            Self::NoExecutor => matches!(other, Self::NoExecutor),
             Self::Other => match other {
                 Self::Other => true,
                 _ => false,
@@ -61,7 +164,27 @@ impl std::cmp::PartialEq for Error {
 
 type LapinErr = lapin::Error;
 
+/// AMQP NOT_FOUND reply code, e.g. for the passive declaration of
+/// the missing queue.
+pub const NOT_FOUND: u16 = 404;
+/// AMQP PRECONDITION_FAILED reply code, e.g. for the re-declaration of
+/// the queue with the different arguments.
+pub const PRECONDITION_FAILED: u16 = 406;
+
 impl Error {
+    /// Returns the AMQP reply code, e.g. [NOT_FOUND], in case the broker
+    /// closed the channel with the protocol error.
+    ///
+    /// [NOT_FOUND]: constant.NOT_FOUND.html
+    pub fn reply_code(&self) -> Option<u16> {
+        match self {
+            Self::Internal(err) => match err.as_ref() {
+                LapinErr::ProtocolError(err) => Some(err.kind().get_id()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
     fn eq_internal(a: &lapin::Error, b: &lapin::Error) -> bool {
         match a {
             LapinErr::InvalidMethod(a) => match b {
@@ -167,11 +290,11 @@ mod tests {
         }
         let mut tests = [
             Test {
-                data: crate::Error::Internal(lapin::Error::UnexpectedReply),
+                data: crate::Error::Internal(Box::new(lapin::Error::UnexpectedReply)),
                 want: String::from("UnexpectedReply"),
             },
             Test {
-                data: crate::Error::Internal(lapin::Error::ChannelsLimitReached),
+                data: crate::Error::Internal(Box::new(lapin::Error::ChannelsLimitReached)),
                 want: String::from("ChannelsLimitReached"),
             },
         ];
@@ -190,11 +313,11 @@ mod tests {
         }
         let mut tests = [
             Test {
-                data: crate::Error::Internal(lapin::Error::UnexpectedReply),
+                data: crate::Error::Internal(Box::new(lapin::Error::UnexpectedReply)),
                 want: String::from("UnexpectedReply"),
             },
             Test {
-                data: crate::Error::Internal(lapin::Error::ChannelsLimitReached),
+                data: crate::Error::Internal(Box::new(lapin::Error::ChannelsLimitReached)),
                 want: String::from("ChannelsLimitReached"),
             },
         ];
@@ -214,123 +337,123 @@ mod tests {
         let mut tests = [
             Test {
                 data: Some(lapin::Error::UnexpectedReply),
-                want: crate::Error::Internal(lapin::Error::UnexpectedReply),
+                want: crate::Error::Internal(Box::new(lapin::Error::UnexpectedReply)),
             },
             Test {
                 data: Some(lapin::Error::ChannelsLimitReached),
-                want: crate::Error::Internal(lapin::Error::ChannelsLimitReached),
+                want: crate::Error::Internal(Box::new(lapin::Error::ChannelsLimitReached)),
             },
             Test {
                 data: Some(lapin::Error::InvalidChannelState(
                     lapin::ChannelState::Initial,
                 )),
-                want: crate::Error::Internal(lapin::Error::InvalidChannelState(
+                want: crate::Error::Internal(Box::new(lapin::Error::InvalidChannelState(
                     lapin::ChannelState::Initial,
-                )),
+                ))),
             },
             Test {
                 data: Some(lapin::Error::InvalidChannelState(
                     lapin::ChannelState::Connected,
                 )),
-                want: crate::Error::Internal(lapin::Error::InvalidChannelState(
+                want: crate::Error::Internal(Box::new(lapin::Error::InvalidChannelState(
                     lapin::ChannelState::Connected,
-                )),
+                ))),
             },
             Test {
                 data: Some(lapin::Error::InvalidChannelState(
                     lapin::ChannelState::Closing,
                 )),
-                want: crate::Error::Internal(lapin::Error::InvalidChannelState(
+                want: crate::Error::Internal(Box::new(lapin::Error::InvalidChannelState(
                     lapin::ChannelState::Closing,
-                )),
+                ))),
             },
             Test {
                 data: Some(lapin::Error::InvalidChannelState(
                     lapin::ChannelState::Closed,
                 )),
-                want: crate::Error::Internal(lapin::Error::InvalidChannelState(
+                want: crate::Error::Internal(Box::new(lapin::Error::InvalidChannelState(
                     lapin::ChannelState::Closed,
-                )),
+                ))),
             },
             Test {
                 data: Some(lapin::Error::InvalidChannelState(
                     lapin::ChannelState::Error,
                 )),
-                want: crate::Error::Internal(lapin::Error::InvalidChannelState(
+                want: crate::Error::Internal(Box::new(lapin::Error::InvalidChannelState(
                     lapin::ChannelState::Error,
-                )),
+                ))),
             },
             Test {
                 data: Some(lapin::Error::InvalidChannelState(
                     lapin::ChannelState::SendingContent(1024),
                 )),
-                want: crate::Error::Internal(lapin::Error::InvalidChannelState(
+                want: crate::Error::Internal(Box::new(lapin::Error::InvalidChannelState(
                     lapin::ChannelState::SendingContent(1024),
-                )),
+                ))),
             },
             Test {
                 data: Some(lapin::Error::InvalidConnectionState(
                     lapin::ConnectionState::Initial,
                 )),
-                want: crate::Error::Internal(lapin::Error::InvalidConnectionState(
+                want: crate::Error::Internal(Box::new(lapin::Error::InvalidConnectionState(
                     lapin::ConnectionState::Initial,
-                )),
+                ))),
             },
             Test {
                 data: Some(lapin::Error::InvalidConnectionState(
                     lapin::ConnectionState::Connected,
                 )),
-                want: crate::Error::Internal(lapin::Error::InvalidConnectionState(
+                want: crate::Error::Internal(Box::new(lapin::Error::InvalidConnectionState(
                     lapin::ConnectionState::Connected,
-                )),
+                ))),
             },
             Test {
                 data: Some(lapin::Error::InvalidConnectionState(
                     lapin::ConnectionState::Closing,
                 )),
-                want: crate::Error::Internal(lapin::Error::InvalidConnectionState(
+                want: crate::Error::Internal(Box::new(lapin::Error::InvalidConnectionState(
                     lapin::ConnectionState::Closing,
-                )),
+                ))),
             },
             Test {
                 data: Some(lapin::Error::InvalidConnectionState(
                     lapin::ConnectionState::Closed,
                 )),
-                want: crate::Error::Internal(lapin::Error::InvalidConnectionState(
+                want: crate::Error::Internal(Box::new(lapin::Error::InvalidConnectionState(
                     lapin::ConnectionState::Closed,
-                )),
+                ))),
             },
             Test {
                 data: Some(lapin::Error::InvalidConnectionState(
                     lapin::ConnectionState::Error,
                 )),
-                want: crate::Error::Internal(lapin::Error::InvalidConnectionState(
+                want: crate::Error::Internal(Box::new(lapin::Error::InvalidConnectionState(
                     lapin::ConnectionState::Error,
-                )),
+                ))),
             },
             Test {
                 data: Some(lapin::Error::SerialisationError(Arc::new(
                     cookie_factory::GenError::BufferTooSmall(1),
                 ))),
-                want: crate::Error::Internal(lapin::Error::SerialisationError(Arc::new(
+                want: crate::Error::Internal(Box::new(lapin::Error::SerialisationError(Arc::new(
                     cookie_factory::GenError::BufferTooSmall(1),
-                ))),
+                )))),
             },
             Test {
                 data: Some(lapin::Error::SerialisationError(Arc::new(
                     cookie_factory::GenError::BufferTooBig(1024 * 1024 * 1024),
                 ))),
-                want: crate::Error::Internal(lapin::Error::SerialisationError(Arc::new(
+                want: crate::Error::Internal(Box::new(lapin::Error::SerialisationError(Arc::new(
                     cookie_factory::GenError::BufferTooBig(1024 * 1024 * 1024),
-                ))),
+                )))),
             },
             Test {
                 data: Some(lapin::Error::SerialisationError(Arc::new(
                     cookie_factory::GenError::InvalidOffset,
                 ))),
-                want: crate::Error::Internal(lapin::Error::SerialisationError(Arc::new(
+                want: crate::Error::Internal(Box::new(lapin::Error::SerialisationError(Arc::new(
                     cookie_factory::GenError::InvalidOffset,
-                ))),
+                )))),
             },
             Test {
                 data: Some(lapin::Error::SerialisationError(Arc::new(
@@ -339,21 +462,20 @@ mod tests {
                         "not found",
                     )),
                 ))),
-                want: crate::Error::Internal(lapin::Error::SerialisationError(Arc::new(
+                want: crate::Error::Internal(Box::new(lapin::Error::SerialisationError(Arc::new(
                     cookie_factory::GenError::IoError(cookie_factory::lib::std::io::Error::new(
                         io::ErrorKind::NotFound,
                         "not found",
                     )),
-                ))),
+                )))),
             },
             Test {
                 data: Some(lapin::Error::IOError(Arc::new(io::Error::new(
                     ErrorKind::Interrupted,
                     "interrupted",
                 )))),
-                want: crate::Error::Internal(lapin::Error::IOError(Arc::new(io::Error::new(
-                    ErrorKind::Interrupted,
-                    "interrupted",
+                want: crate::Error::Internal(Box::new(lapin::Error::IOError(Arc::new(
+                    io::Error::new(ErrorKind::Interrupted, "interrupted"),
                 )))),
             },
         ];
@@ -363,4 +485,38 @@ mod tests {
             assert_eq!(t.want, got);
         }
     }
+    #[test]
+    fn reply_code() {
+        use lapin::protocol::AMQPError;
+        struct Test {
+            data: super::Error,
+            want: Option<u16>,
+        }
+        let tests = [
+            Test {
+                data: super::Error::Internal(Box::new(lapin::Error::ProtocolError(
+                    AMQPError::from_id(super::NOT_FOUND, "NOT_FOUND".into()).unwrap(),
+                ))),
+                want: Some(super::NOT_FOUND),
+            },
+            Test {
+                data: super::Error::Internal(Box::new(lapin::Error::ProtocolError(
+                    AMQPError::from_id(super::PRECONDITION_FAILED, "PRECONDITION_FAILED".into())
+                        .unwrap(),
+                ))),
+                want: Some(super::PRECONDITION_FAILED),
+            },
+            Test {
+                data: super::Error::Internal(Box::new(lapin::Error::InvalidAck)),
+                want: None,
+            },
+            Test {
+                data: super::Error::NotFound(String::from("queue 'orders'")),
+                want: None,
+            },
+        ];
+        for t in &tests {
+            assert_eq!(t.want, t.data.reply_code());
+        }
+    }
 }
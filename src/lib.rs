@@ -3,17 +3,52 @@
 //!
 //! [lapin]: https://crates.io/crates/lapin
 //! [amqp]: https://www.amqp.org
-pub use client::{Client, Connection};
-pub use consume::{Consumer, ConsumerBuilder};
+pub use breaker::{CircuitBreaker, CircuitEvents, CircuitState};
+pub use client::{BlockedEvent, BlockedEvents, Client, Connection, ConnectionConfig};
+pub use consume::{Consumer, ConsumerBuilder, Overflow, QueueType, Scheduling, StreamOffset};
+#[cfg(feature = "sqlite")]
+pub use dedup::SqliteDedupStore;
+pub use dedup::{DedupProcessor, DedupStore, LruDedupStore};
 pub use error::Error;
-pub use message::{Message, MessageError, MessagePeek, MessageProcess};
-pub use produce::{Producer, ProducerBuilder};
+pub use limit::{RateLimitPolicy, RateLimiter};
+pub use message::{
+    Acker, BatchProcess, DropPolicy, Message, MessageError, MessagePeek, MessageProcess,
+};
+#[cfg(feature = "outbox")]
+pub use outbox::{Outbox, OutboxRelay};
+pub use pool::{ConsumerPool, PoolStatus, WorkerState};
+pub use produce::{BlockedPolicy, DelayMode, Producer, ProducerBuilder};
+pub use pull::{Puller, PullerBuilder};
+#[cfg(feature = "futures-executor")]
+pub use runtime::ThreadPoolExecutor;
+#[cfg(feature = "async-std")]
+pub use runtime::{AsyncStdExecutor, AsyncStdTimer};
+pub use runtime::{Executor, FuturesTimer, Timer};
+#[cfg(feature = "tokio")]
+pub use runtime::{TokioExecutor, TokioTimer};
+pub use shard::ShardedConsumerGroup;
+pub use topology::Topology;
+pub use transaction::Transaction;
 
+pub mod breaker;
 pub mod client;
 pub mod consume;
+pub mod dedup;
 pub mod error;
+pub mod limit;
 pub mod message;
+pub mod metrics;
+#[cfg(feature = "outbox")]
+pub mod outbox;
+pub mod pool;
 pub mod produce;
+pub mod pull;
+pub mod runtime;
+pub mod shard;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+pub mod topology;
+pub mod transaction;
 
 /// Crate local type aliases for less typing.  Those are meant for the
 /// internal use cases and won't be published.
@@ -33,9 +68,11 @@ const EPHEMERAL_QUEUE: &str = "";
 /// We may add items to this over time as they become ubiquitous as well.
 pub mod prelude {
     #[doc(no_inline)]
-    pub use crate::{Client, Connection};
+    pub use crate::{Client, Connection, ConnectionConfig};
     #[doc(no_inline)]
     pub use crate::{Consumer, ConsumerBuilder};
     #[doc(no_inline)]
     pub use crate::{Producer, ProducerBuilder};
+    #[doc(no_inline)]
+    pub use crate::{Puller, PullerBuilder};
 }
@@ -0,0 +1,250 @@
+// SPDX-License-Identifier: Apache-2.0 AND MIT
+//! `RateLimiter` struct and `RateLimitPolicy` enum
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// [RateLimiter] policy once the rate limit is reached.
+///
+/// [RateLimiter]: struct.RateLimiter.html
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RateLimitPolicy {
+    /// Wait until the tokens are available.
+    #[default]
+    Wait,
+    /// Fail fast with [Error::RateLimited].
+    ///
+    /// [Error::RateLimited]: ../error/enum.Error.html#variant.RateLimited
+    Fail,
+}
+
+/// A client-side token bucket rate limiter of the messages and the bytes
+/// per second, which allows the burst of up to a second worth of them.
+/// It waits over the [Timer], [FuturesTimer] by default.
+///
+/// [Timer]: ../runtime/trait.Timer.html
+/// [FuturesTimer]: ../runtime/struct.FuturesTimer.html
+#[derive(Clone)]
+pub struct RateLimiter {
+    msgs: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+    policy: RateLimitPolicy,
+    timer: Arc<dyn crate::Timer>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self {
+            msgs: None,
+            bytes: None,
+            policy: RateLimitPolicy::default(),
+            timer: Arc::new(crate::runtime::FuturesTimer),
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Limit the number of the messages per second.  Zero removes
+    /// the limit.
+    pub fn message_rate(&mut self, per_sec: u32) -> &mut Self {
+        self.msgs = match per_sec {
+            0 => None,
+            per_sec => Some(TokenBucket::new(f64::from(per_sec))),
+        };
+        self
+    }
+    /// Limit the number of the message bytes per second.  Zero removes
+    /// the limit.
+    pub fn byte_rate(&mut self, per_sec: u64) -> &mut Self {
+        self.bytes = match per_sec {
+            0 => None,
+            per_sec => Some(TokenBucket::new(per_sec as f64)),
+        };
+        self
+    }
+    /// Specify the [RateLimitPolicy], which is [RateLimitPolicy::Wait]
+    /// by default.
+    ///
+    /// [RateLimitPolicy]: enum.RateLimitPolicy.html
+    /// [RateLimitPolicy::Wait]: enum.RateLimitPolicy.html#variant.Wait
+    pub fn policy(&mut self, policy: RateLimitPolicy) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+    /// Specify the [Timer] to wait for the tokens, [FuturesTimer]
+    /// by default.
+    ///
+    /// [Timer]: ../runtime/trait.Timer.html
+    /// [FuturesTimer]: ../runtime/struct.FuturesTimer.html
+    pub fn timer(&mut self, timer: Arc<dyn crate::Timer>) -> &mut Self {
+        self.timer = timer;
+        self
+    }
+    /// Returns `true` in case either of the rates is limited.
+    pub fn is_limited(&self) -> bool {
+        self.msgs.is_some() || self.bytes.is_some()
+    }
+    /// Takes the tokens for a message of `len` bytes, waiting for them or
+    /// failing with [Error::RateLimited], depending on the [RateLimitPolicy].
+    ///
+    /// [Error::RateLimited]: ../error/enum.Error.html#variant.RateLimited
+    /// [RateLimitPolicy]: enum.RateLimitPolicy.html
+    pub async fn acquire(&mut self, len: usize) -> crate::Result<()> {
+        let now = Instant::now();
+        let len = len as f64;
+        match self.policy {
+            RateLimitPolicy::Fail => {
+                let msgs = match self.msgs.as_mut() {
+                    Some(bucket) => bucket.ready(1.0, now),
+                    None => true,
+                };
+                let bytes = match self.bytes.as_mut() {
+                    Some(bucket) => bucket.ready(len, now),
+                    None => true,
+                };
+                if !msgs || !bytes {
+                    return Err(crate::Error::RateLimited);
+                }
+                self.reserve(len, now);
+            }
+            RateLimitPolicy::Wait => {
+                let wait = self.reserve(len, now);
+                if wait > Duration::default() {
+                    self.timer.delay(wait).await;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn reserve(&mut self, len: f64, now: Instant) -> Duration {
+        let msgs = self.msgs.as_mut().map(|b| b.reserve(1.0, now));
+        let bytes = self.bytes.as_mut().map(|b| b.reserve(len, now));
+        msgs.max(bytes).unwrap_or_default()
+    }
+}
+
+/// A token bucket, which refills at the rate per second up to the rate.
+#[derive(Clone, Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            tokens: rate,
+            last: Instant::now(),
+        }
+    }
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+    }
+    /// Returns `true` in case the tokens are available, or the bucket is
+    /// full for the request larger than the bucket.
+    fn ready(&mut self, n: f64, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= n.min(self.rate)
+    }
+    /// Takes the tokens, into the debt if necessary, and returns the time
+    /// to wait until the debt is paid off.
+    fn reserve(&mut self, n: f64, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= n;
+        if self.tokens >= 0.0 || self.rate <= 0.0 {
+            return Duration::default();
+        }
+        Duration::from_secs_f64(-self.tokens / self.rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn reserve() {
+        struct Test {
+            name: &'static str,
+            data: Vec<(u64, f64)>,
+            want: Vec<u64>,
+        }
+        // (elapsed milliseconds, tokens) with the 10 tokens per second rate.
+        let tests = [
+            Test {
+                name: "within burst",
+                data: vec![(0, 5.0), (0, 5.0)],
+                want: vec![0, 0],
+            },
+            Test {
+                name: "over burst",
+                data: vec![(0, 10.0), (0, 1.0), (0, 1.0)],
+                want: vec![0, 100, 200],
+            },
+            Test {
+                name: "refilled",
+                data: vec![(0, 10.0), (500, 5.0), (500, 6.0)],
+                want: vec![0, 0, 100],
+            },
+            Test {
+                name: "larger than burst",
+                data: vec![(0, 20.0)],
+                want: vec![1000],
+            },
+        ];
+        for t in &tests {
+            let start = Instant::now();
+            let mut bucket = super::TokenBucket {
+                rate: 10.0,
+                tokens: 10.0,
+                last: start,
+            };
+            let mut now = start;
+            let mut got = Vec::new();
+            for (elapsed, n) in &t.data {
+                now += Duration::from_millis(*elapsed);
+                got.push(bucket.reserve(*n, now).as_millis() as u64);
+            }
+            assert_eq!(t.want, got, "{}", t.name);
+        }
+    }
+    #[test]
+    fn ready() {
+        struct Test {
+            data: (f64, f64),
+            want: bool,
+        }
+        // (available tokens, requested tokens) with the 10 tokens per
+        // second rate.
+        let tests = [
+            Test {
+                data: (10.0, 1.0),
+                want: true,
+            },
+            Test {
+                data: (0.5, 1.0),
+                want: false,
+            },
+            Test {
+                data: (10.0, 20.0),
+                want: true,
+            },
+            Test {
+                data: (9.0, 20.0),
+                want: false,
+            },
+        ];
+        for t in &tests {
+            let now = Instant::now();
+            let mut bucket = super::TokenBucket {
+                rate: 10.0,
+                tokens: t.data.0,
+                last: now,
+            };
+            assert_eq!(t.want, bucket.ready(t.data.1, now), "{:?}", t.data);
+        }
+    }
+}
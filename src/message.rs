@@ -1,12 +1,55 @@
 // SPDX-License-Identifier: Apache-2.0 AND MIT
-//! `Message` struct, `MessagePeek` and `MessageProcess` trait
+//! `Message` struct, `MessagePeek`, `MessageProcess` and `BatchProcess` trait
 use async_trait::async_trait;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-/// A zero-cost [lapin::message::Delivery] [newtype].
+/// A zero-cost [lapin::message::Delivery] [newtype], with the optional
+/// [Acker] handle and the name of the queue it's consumed from.
 ///
 /// [lapin::message::Delivery]: https://docs.rs/lapin/latest/lapin/message/struct.Delivery.html
 /// [newtype]: https://doc.rust-lang.org/1.0.0/style/features/types/newtype.html
-pub struct Message(lapin::message::Delivery);
+/// [Acker]: struct.Acker.html
+pub struct Message(lapin::message::Delivery, Option<Acker>, Option<Arc<str>>);
+
+/// An acknowledgement handle of the [Message] streamed by [Consumer].
+/// It's cheap to clone and usable from any task, and a message is
+/// acknowledged at most once, whichever clone is used.
+///
+/// [Message]: struct.Message.html
+/// [Consumer]: ../consume/struct.Consumer.html
+#[derive(Clone)]
+pub struct Acker(Arc<AckerInner>);
+
+struct AckerInner {
+    ch: lapin::Channel,
+    delivery_tag: u64,
+    reply_to: Option<String>,
+    correlation_id: Option<String>,
+    ex: String,
+    tx_props: lapin::BasicProperties,
+    tx_opts: lapin::options::BasicPublishOptions,
+    policy: DropPolicy,
+    settled: AtomicBool,
+    /// The exchange and the queue of the message, for the metrics.
+    source: (String, String),
+}
+
+/// [Acker] policy for the [Message] dropped without being acknowledged.
+///
+/// [Acker]: struct.Acker.html
+/// [Message]: struct.Message.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DropPolicy {
+    /// Leave the message unacknowledged, until the channel is closed.
+    Leave,
+    /// Ack the message.
+    Ack,
+    /// Reject the message without the requeue.
+    Reject,
+    /// Nack the message with the requeue.
+    Requeue,
+}
 
 /// Error actions used both by [MessagePeek] and [MessageProcess]
 /// trait implementations.
@@ -20,12 +63,26 @@ pub enum MessageError {
     Reject,
     /// Nack a message.
     Nack,
+    /// Nack a message with the requeue, e.g. on the transient failure, so
+    /// that [Consumer::run] processes it again.
+    ///
+    /// [Consumer::run]: ../consume/struct.Consumer.html#method.run
+    Requeue,
 }
 
 impl Message {
     #[inline]
     pub fn new(delivery: lapin::message::Delivery) -> Self {
-        Self(delivery)
+        Self(delivery, None, None)
+    }
+    #[inline]
+    pub(crate) fn with_queue(delivery: lapin::message::Delivery, queue: Arc<str>) -> Self {
+        Self(delivery, None, Some(queue))
+    }
+    #[inline]
+    pub(crate) fn with_acker(mut self, acker: Acker) -> Self {
+        self.1 = Some(acker);
+        self
     }
     #[inline]
     pub fn data(&self) -> &[u8] {
@@ -35,6 +92,44 @@ impl Message {
     pub fn delivery_tag(&self) -> u64 {
         self.0.delivery_tag
     }
+    /// Returns the name of the queue the message is consumed from, in case
+    /// the message is streamed by [Consumer].
+    ///
+    /// [Consumer]: ../consume/struct.Consumer.html
+    #[inline]
+    pub fn queue(&self) -> Option<&str> {
+        self.2.as_ref().map(|queue| queue.as_ref())
+    }
+    /// Returns the exchange the message is published to.
+    #[inline]
+    pub fn exchange(&self) -> &str {
+        self.0.exchange.as_str()
+    }
+    /// Returns the routing key the message is published with.
+    #[inline]
+    pub fn routing_key(&self) -> &str {
+        self.0.routing_key.as_str()
+    }
+    #[inline]
+    pub fn message_id(&self) -> Option<&str> {
+        self.0
+            .properties
+            .message_id()
+            .as_ref()
+            .map(|str| str.as_str())
+    }
+    #[inline]
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.0
+            .properties
+            .correlation_id()
+            .as_ref()
+            .map(|str| str.as_str())
+    }
+    #[inline]
+    pub fn properties(&self) -> &lapin::BasicProperties {
+        &self.0.properties
+    }
     #[inline]
     pub fn reply_to(&self) -> Option<&str> {
         self.0
@@ -43,6 +138,196 @@ impl Message {
             .as_ref()
             .map(|str| str.as_str())
     }
+    /// Returns the `x-delivery-count` header, which the quorum queue sets
+    /// on the redelivered message.
+    pub fn delivery_count(&self) -> Option<u64> {
+        use lapin::types::AMQPValue;
+        use std::convert::TryFrom;
+        let headers = self.0.properties.headers().as_ref()?;
+        match headers.inner().get("x-delivery-count")? {
+            AMQPValue::ShortShortInt(val) => u64::try_from(*val).ok(),
+            AMQPValue::ShortShortUInt(val) => Some(u64::from(*val)),
+            AMQPValue::ShortInt(val) => u64::try_from(*val).ok(),
+            AMQPValue::ShortUInt(val) => Some(u64::from(*val)),
+            AMQPValue::LongInt(val) => u64::try_from(*val).ok(),
+            AMQPValue::LongUInt(val) => Some(u64::from(*val)),
+            AMQPValue::LongLongInt(val) => u64::try_from(*val).ok(),
+            _ => None,
+        }
+    }
+    /// Returns the [Acker] handle, in case the message is streamed by
+    /// [Consumer].
+    ///
+    /// [Acker]: struct.Acker.html
+    /// [Consumer]: ../consume/struct.Consumer.html
+    #[inline]
+    pub fn acker(&self) -> Option<Acker> {
+        self.1.clone()
+    }
+}
+
+impl Acker {
+    pub(crate) fn new(
+        ch: lapin::Channel,
+        msg: &Message,
+        ex: &str,
+        tx_props: lapin::BasicProperties,
+        tx_opts: lapin::options::BasicPublishOptions,
+        policy: DropPolicy,
+    ) -> Self {
+        Self(Arc::new(AckerInner {
+            ch,
+            delivery_tag: msg.delivery_tag(),
+            reply_to: msg.reply_to().map(String::from),
+            correlation_id: msg.correlation_id().map(String::from),
+            ex: String::from(ex),
+            tx_props,
+            tx_opts,
+            policy,
+            settled: AtomicBool::new(false),
+            source: (
+                String::from(msg.exchange()),
+                String::from(msg.queue().unwrap_or_default()),
+            ),
+        }))
+    }
+    /// Returns `true` in case the message is already acknowledged.
+    pub fn is_settled(&self) -> bool {
+        self.0.settled.load(Ordering::SeqCst)
+    }
+    pub async fn ack(&self) -> crate::Result<()> {
+        self.settle()?;
+        self.0
+            .ch
+            .basic_ack(
+                self.0.delivery_tag,
+                lapin::options::BasicAckOptions::default(),
+            )
+            .await
+            .map_err(crate::Error::from)?;
+        crate::metrics::acked(&self.0.source.0, &self.0.source.1);
+        Ok(())
+    }
+    pub async fn nack(&self, requeue: bool) -> crate::Result<()> {
+        self.settle()?;
+        let opts = lapin::options::BasicNackOptions {
+            requeue,
+            ..lapin::options::BasicNackOptions::default()
+        };
+        self.0
+            .ch
+            .basic_nack(self.0.delivery_tag, opts)
+            .await
+            .map_err(crate::Error::from)?;
+        crate::metrics::nacked(&self.0.source.0, &self.0.source.1);
+        Ok(())
+    }
+    pub async fn reject(&self) -> crate::Result<()> {
+        self.settle()?;
+        self.0
+            .ch
+            .basic_reject(
+                self.0.delivery_tag,
+                lapin::options::BasicRejectOptions::default(),
+            )
+            .await
+            .map_err(crate::Error::from)?;
+        crate::metrics::rejected(&self.0.source.0, &self.0.source.1);
+        Ok(())
+    }
+    /// Sends the response, with the request's correlation ID, to
+    /// the `reply_to` queue, if any, and then acks the message.  The message
+    /// stays unacknowledged in case the response is not sent.
+    pub async fn reply(&self, resp: &[u8]) -> crate::Result<()> {
+        self.settle()?;
+        if let Some(reply_to) = &self.0.reply_to {
+            let props = match &self.0.correlation_id {
+                Some(id) => self
+                    .0
+                    .tx_props
+                    .clone()
+                    .with_correlation_id(id.as_str().into()),
+                None => self.0.tx_props.clone(),
+            };
+            let result = self
+                .0
+                .ch
+                .basic_publish(
+                    &self.0.ex,
+                    reply_to,
+                    self.0.tx_opts.clone(),
+                    resp.to_vec(),
+                    props,
+                )
+                .await
+                .map_err(crate::Error::from);
+            if let Err(err) = result {
+                // Leaves the message to be settled again, e.g. rejected.
+                self.unsettle();
+                return Err(err);
+            }
+        }
+        self.0
+            .ch
+            .basic_ack(
+                self.0.delivery_tag,
+                lapin::options::BasicAckOptions::default(),
+            )
+            .await
+            .map_err(crate::Error::from)?;
+        crate::metrics::acked(&self.0.source.0, &self.0.source.1);
+        Ok(())
+    }
+    /// Marks the message as acknowledged, or returns the `InvalidAck`
+    /// error in case it's already acknowledged.
+    pub(crate) fn settle(&self) -> crate::Result<()> {
+        if self.0.settled.swap(true, Ordering::SeqCst) {
+            return Err(crate::Error::from(lapin::Error::InvalidAck));
+        }
+        Ok(())
+    }
+    /// Marks the message as unacknowledged again, e.g. after the
+    /// transaction rollback.
+    pub(crate) fn unsettle(&self) {
+        self.0.settled.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Drop for AckerInner {
+    fn drop(&mut self) {
+        if *self.settled.get_mut() {
+            return;
+        }
+        // lapin sends the frame on call, so we don't need to wait for
+        // the result here.
+        let tag = self.delivery_tag;
+        let (ex, queue) = &self.source;
+        match self.policy {
+            DropPolicy::Leave => {}
+            DropPolicy::Ack => {
+                drop(
+                    self.ch
+                        .basic_ack(tag, lapin::options::BasicAckOptions::default()),
+                );
+                crate::metrics::acked(ex, queue);
+            }
+            DropPolicy::Reject => {
+                drop(
+                    self.ch
+                        .basic_reject(tag, lapin::options::BasicRejectOptions::default()),
+                );
+                crate::metrics::rejected(ex, queue);
+            }
+            DropPolicy::Requeue => {
+                let opts = lapin::options::BasicNackOptions {
+                    requeue: true,
+                    ..lapin::options::BasicNackOptions::default()
+                };
+                drop(self.ch.basic_nack(tag, opts));
+                crate::metrics::nacked(ex, queue);
+            }
+        }
+    }
 }
 
 /// A trait to peek the [Message] and returns success or error.
@@ -80,6 +365,24 @@ impl Clone for Box<dyn MessageProcess + Send + Sync> {
     }
 }
 
+/// A trait to process the batch of [Message]s at once, e.g. for the bulk
+/// database writes.
+///
+/// [Message]: struct.Message.html
+#[async_trait]
+pub trait BatchProcess {
+    /// Async method to process a batch of messages.
+    async fn process(&mut self, msgs: &[Message]) -> Result<(), MessageError>;
+    fn boxed_clone(&self) -> Box<dyn BatchProcess + Send + Sync>;
+}
+
+// https://users.rust-lang.org/t/solved-is-it-possible-to-clone-a-boxed-trait-object/1714/6
+impl Clone for Box<dyn BatchProcess + Send + Sync> {
+    fn clone(&self) -> Box<dyn BatchProcess + Send + Sync> {
+        self.boxed_clone()
+    }
+}
+
 /// A [MessagePeek] implementation which does nothing.
 ///
 /// [MessagePeek]: trait.MessagePeek.html
@@ -131,3 +434,102 @@ impl MessageProcess for EchoProcessor {
         Box::new((*self).clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use lapin::types::{AMQPValue, FieldTable};
+    #[test]
+    fn delivery_count() {
+        struct Test {
+            data: Option<AMQPValue>,
+            want: Option<u64>,
+        }
+        let tests = [
+            Test {
+                data: None,
+                want: None,
+            },
+            Test {
+                data: Some(AMQPValue::LongLongInt(3)),
+                want: Some(3),
+            },
+            Test {
+                data: Some(AMQPValue::LongInt(2)),
+                want: Some(2),
+            },
+            Test {
+                data: Some(AMQPValue::LongInt(-1)),
+                want: None,
+            },
+            Test {
+                data: Some(AMQPValue::ShortShortInt(i8::MIN)),
+                want: None,
+            },
+            Test {
+                data: Some(AMQPValue::LongUInt(u32::MAX)),
+                want: Some(u64::from(u32::MAX)),
+            },
+            Test {
+                data: Some(AMQPValue::LongString("3".into())),
+                want: None,
+            },
+        ];
+        for t in &tests {
+            let mut headers = FieldTable::default();
+            if let Some(val) = &t.data {
+                headers.insert("x-delivery-count".into(), val.clone());
+            }
+            let delivery = lapin::message::Delivery {
+                delivery_tag: 1,
+                exchange: "".into(),
+                routing_key: "".into(),
+                redelivered: t.data.is_some(),
+                properties: lapin::BasicProperties::default().with_headers(headers),
+                data: Vec::new(),
+            };
+            let got = super::Message::new(delivery);
+            assert_eq!(t.want, got.delivery_count());
+        }
+    }
+    #[test]
+    fn source() {
+        struct Test {
+            data: (Option<&'static str>, &'static str, &'static str),
+            want: Option<&'static str>,
+        }
+        let tests = [
+            Test {
+                // The routing key doesn't tell the queue, even over
+                // the default exchange.
+                data: (None, "", "hello"),
+                want: None,
+            },
+            Test {
+                data: (Some("orders.high"), "orders", "orders.created"),
+                want: Some("orders.high"),
+            },
+            Test {
+                data: (Some("amq.gen-JzTY20BRgKO"), "events", "user.signup"),
+                want: Some("amq.gen-JzTY20BRgKO"),
+            },
+        ];
+        for t in &tests {
+            let (queue, exchange, routing_key) = t.data;
+            let delivery = lapin::message::Delivery {
+                delivery_tag: 1,
+                exchange: exchange.into(),
+                routing_key: routing_key.into(),
+                redelivered: false,
+                properties: lapin::BasicProperties::default(),
+                data: Vec::new(),
+            };
+            let got = match queue {
+                Some(queue) => super::Message::with_queue(delivery, queue.into()),
+                None => super::Message::new(delivery),
+            };
+            assert_eq!(t.want, got.queue(), "{:?}", t.data);
+            assert_eq!(exchange, got.exchange(), "{:?}", t.data);
+            assert_eq!(routing_key, got.routing_key(), "{:?}", t.data);
+        }
+    }
+}
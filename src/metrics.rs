@@ -0,0 +1,254 @@
+// SPDX-License-Identifier: Apache-2.0 AND MIT
+//! Metric names recorded through the [metrics] facade
+//!
+//! The producers, the consumers and the connections record the metrics
+//! with the `metrics` feature, labeled by the `exchange` and the `queue`,
+//! to the recorder installed by the application, e.g. the Prometheus
+//! exporter of [metrics-exporter-prometheus].  They're no-op otherwise.
+//!
+//! [metrics]: https://docs.rs/metrics
+//! [metrics-exporter-prometheus]: https://docs.rs/metrics-exporter-prometheus
+#![cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+use std::time::Duration;
+
+/// Counter of the messages published by [Producer].
+///
+/// [Producer]: ../produce/struct.Producer.html
+pub const PUBLISHED: &str = "async_mq_published_total";
+/// Counter of the messages confirmed by the broker, with the publisher
+/// confirms.
+pub const CONFIRMED: &str = "async_mq_confirmed_total";
+/// Counter of the unroutable messages returned by the broker, with the
+/// publisher confirms.
+pub const RETURNED: &str = "async_mq_returned_total";
+/// Counter of the messages consumed by [Consumer].
+///
+/// [Consumer]: ../consume/struct.Consumer.html
+pub const CONSUMED: &str = "async_mq_consumed_total";
+/// Counter of the consumed messages acked.
+pub const ACKED: &str = "async_mq_acked_total";
+/// Counter of the consumed messages rejected.
+pub const REJECTED: &str = "async_mq_rejected_total";
+/// Counter of the consumed messages nacked.
+pub const NACKED: &str = "async_mq_nacked_total";
+/// Histogram of the [Producer::rpc] latency in seconds.
+///
+/// [Producer::rpc]: ../produce/struct.Producer.html#method.rpc
+pub const RPC_DURATION: &str = "async_mq_rpc_duration_seconds";
+/// Histogram of the [Consumer::run] processing duration in seconds.
+///
+/// [Consumer::run]: ../consume/struct.Consumer.html#method.run
+pub const PROCESS_DURATION: &str = "async_mq_process_duration_seconds";
+/// Gauge of the messages being processed by [Consumer::run].
+///
+/// [Consumer::run]: ../consume/struct.Consumer.html#method.run
+pub const IN_FLIGHT: &str = "async_mq_in_flight";
+/// Counter of the connections opened by [Client].
+///
+/// [Client]: ../client/struct.Client.html
+pub const CONNECTIONS: &str = "async_mq_connections_total";
+/// Counter of the channels opened over [Connection].
+///
+/// [Connection]: ../client/struct.Connection.html
+pub const CHANNELS: &str = "async_mq_channels_total";
+/// Histogram of the time waited for the blocked [Connection] in seconds.
+///
+/// [Connection]: ../client/struct.Connection.html
+pub const BLOCKED_DURATION: &str = "async_mq_blocked_duration_seconds";
+
+pub(crate) fn published(exchange: &str, queue: &str) {
+    increment(PUBLISHED, exchange, queue, 1);
+}
+pub(crate) fn confirmed(exchange: &str, queue: &str) {
+    increment(CONFIRMED, exchange, queue, 1);
+}
+pub(crate) fn returned(exchange: &str, queue: &str, count: usize) {
+    if count > 0 {
+        increment(RETURNED, exchange, queue, count as u64);
+    }
+}
+pub(crate) fn consumed(msg: &crate::Message) {
+    increment(CONSUMED, msg.exchange(), msg.queue().unwrap_or_default(), 1);
+}
+pub(crate) fn acked(exchange: &str, queue: &str) {
+    increment(ACKED, exchange, queue, 1);
+}
+pub(crate) fn rejected(exchange: &str, queue: &str) {
+    increment(REJECTED, exchange, queue, 1);
+}
+pub(crate) fn nacked(exchange: &str, queue: &str) {
+    increment(NACKED, exchange, queue, 1);
+}
+pub(crate) fn rpc(exchange: &str, queue: &str, elapsed: Duration) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(RPC_DURATION, labels(exchange, queue)).record(elapsed);
+}
+/// Marks the message in flight, until the returned [InFlight] guard is
+/// dropped, even by the cancelled or the panicked processing.
+///
+/// [InFlight]: struct.InFlight.html
+pub(crate) fn processing(msg: &crate::Message) -> InFlight {
+    #[cfg(feature = "metrics")]
+    let gauge = ::metrics::gauge!(
+        IN_FLIGHT,
+        labels(msg.exchange(), msg.queue().unwrap_or_default())
+    );
+    #[cfg(feature = "metrics")]
+    gauge.increment(1.0);
+    InFlight {
+        #[cfg(feature = "metrics")]
+        gauge,
+    }
+}
+pub(crate) fn process_duration(msg: &crate::Message, elapsed: Duration) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(
+        PROCESS_DURATION,
+        labels(msg.exchange(), msg.queue().unwrap_or_default())
+    )
+    .record(elapsed);
+}
+pub(crate) fn connection() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(CONNECTIONS).increment(1);
+}
+pub(crate) fn channel() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(CHANNELS).increment(1);
+}
+pub(crate) fn blocked(elapsed: Duration) {
+    #[cfg(feature = "metrics")]
+    ::metrics::histogram!(BLOCKED_DURATION).record(elapsed);
+}
+
+/// An in-flight message guard, returned by [processing].
+///
+/// [processing]: fn.processing.html
+pub(crate) struct InFlight {
+    #[cfg(feature = "metrics")]
+    gauge: ::metrics::Gauge,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        #[cfg(feature = "metrics")]
+        self.gauge.decrement(1.0);
+    }
+}
+
+fn increment(name: &'static str, exchange: &str, queue: &str, count: u64) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(name, labels(exchange, queue)).increment(count);
+}
+
+#[cfg(feature = "metrics")]
+fn labels(exchange: &str, queue: &str) -> Vec<::metrics::Label> {
+    vec![
+        ::metrics::Label::new("exchange", exchange.to_string()),
+        ::metrics::Label::new("queue", queue.to_string()),
+    ]
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::MetricKind;
+
+    #[test]
+    fn increment() {
+        struct Test {
+            data: Vec<(&'static str, &'static str)>,
+            want: Vec<(&'static str, &'static str, u64)>,
+        }
+        let tests = [
+            Test {
+                data: vec![("ex", "q")],
+                want: vec![("ex", "q", 1)],
+            },
+            Test {
+                data: vec![("ex", "q"), ("ex", "q"), ("", "q")],
+                want: vec![("", "q", 1), ("ex", "q", 2)],
+            },
+        ];
+        for t in &tests {
+            let recorder = DebuggingRecorder::new();
+            let snapshotter = recorder.snapshotter();
+            metrics::with_local_recorder(&recorder, || {
+                for (ex, queue) in &t.data {
+                    super::published(ex, queue);
+                }
+            });
+            let mut got: Vec<_> = snapshotter
+                .snapshot()
+                .into_vec()
+                .into_iter()
+                .filter_map(|(key, _, _, value)| match (key.kind(), value) {
+                    (MetricKind::Counter, DebugValue::Counter(n)) => {
+                        assert_eq!(super::PUBLISHED, key.key().name());
+                        let mut labels = key.key().labels();
+                        let ex = labels.next().unwrap().value().to_string();
+                        let queue = labels.next().unwrap().value().to_string();
+                        Some((ex, queue, n))
+                    }
+                    _ => None,
+                })
+                .collect();
+            got.sort();
+            let want: Vec<_> = t
+                .want
+                .iter()
+                .map(|(ex, queue, n)| (ex.to_string(), queue.to_string(), *n))
+                .collect();
+            assert_eq!(want, got, "{:?}", t.data);
+        }
+    }
+    #[test]
+    fn in_flight() {
+        struct Test {
+            data: usize,
+            want: (f64, f64),
+        }
+        let tests = [
+            Test {
+                data: 0,
+                want: (0.0, 0.0),
+            },
+            Test {
+                data: 3,
+                want: (3.0, -3.0),
+            },
+        ];
+        let msg = crate::Message::new(lapin::message::Delivery {
+            delivery_tag: 1,
+            exchange: "ex".into(),
+            routing_key: "".into(),
+            redelivered: false,
+            properties: lapin::BasicProperties::default(),
+            data: Vec::new(),
+        });
+        for t in &tests {
+            let recorder = DebuggingRecorder::new();
+            let snapshotter = recorder.snapshotter();
+            let gauge = || {
+                snapshotter
+                    .snapshot()
+                    .into_vec()
+                    .into_iter()
+                    .map(|(key, _, _, value)| match value {
+                        DebugValue::Gauge(n) if key.key().name() == super::IN_FLIGHT => n.0,
+                        _ => 0.0,
+                    })
+                    .sum::<f64>()
+            };
+            let got = metrics::with_local_recorder(&recorder, || {
+                let guards: Vec<_> = (0..t.data).map(|_| super::processing(&msg)).collect();
+                let running = gauge();
+                // The cancelled processing drops the guards, and the snapshot
+                // drains the gauge, i.e. returns the delta.
+                drop(guards);
+                (running, gauge())
+            });
+            assert_eq!(t.want, got, "{}", t.data);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+// SPDX-License-Identifier: Apache-2.0 AND MIT
+//! `Outbox` and `OutboxRelay` structs
+use std::time::{Duration, SystemTime};
+
+/// The default outbox table name.
+pub const DEFAULT_TABLE: &str = "async_mq_outbox";
+/// The default maximum number of messages relayed at once.
+pub const DEFAULT_RELAY_BATCH: usize = 100;
+/// The default time to wait before polling the empty outbox again.
+pub const DEFAULT_RELAY_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// A transactional outbox over the SQLite table, which stores the outgoing
+/// messages inside the caller's database transaction, so that they're
+/// published by [OutboxRelay] only once the transaction is committed.
+///
+/// [OutboxRelay]: struct.OutboxRelay.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outbox {
+    table: String,
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self {
+            table: String::from(DEFAULT_TABLE),
+        }
+    }
+}
+
+impl Outbox {
+    /// Creates the outbox over the specified table, which name consists of
+    /// the ASCII alphanumerics and the underscores.
+    pub fn new(table: &str) -> crate::Result<Self> {
+        crate::sqlite::check_table("outbox", table)?;
+        Ok(Self {
+            table: String::from(table),
+        })
+    }
+    /// Creates the outbox table, unless it exists.
+    pub fn init(&self, db: &rusqlite::Connection) -> crate::Result<()> {
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                payload BLOB NOT NULL,
+                created_at INTEGER NOT NULL,
+                delivered_at INTEGER
+            )",
+            self.table
+        );
+        db.execute(&sql, rusqlite::NO_PARAMS)?;
+        Ok(())
+    }
+    /// Stores the message and returns its ID.  Pass the caller's
+    /// [rusqlite::Transaction], which derefs to the connection, so that
+    /// the message is stored only in case the transaction is committed.
+    ///
+    /// [rusqlite::Transaction]: https://docs.rs/rusqlite/latest/rusqlite/struct.Transaction.html
+    pub fn store(&self, db: &rusqlite::Connection, msg: &[u8]) -> crate::Result<i64> {
+        let sql = format!(
+            "INSERT INTO {} (payload, created_at) VALUES (?1, ?2)",
+            self.table
+        );
+        db.execute(&sql, rusqlite::params![msg, now()])?;
+        Ok(db.last_insert_rowid())
+    }
+    /// Returns up to `max` undelivered messages with their IDs, in order.
+    pub fn pending(
+        &self,
+        db: &rusqlite::Connection,
+        max: usize,
+    ) -> crate::Result<Vec<(i64, Vec<u8>)>> {
+        let sql = format!(
+            "SELECT id, payload FROM {} WHERE delivered_at IS NULL ORDER BY id LIMIT ?1",
+            self.table
+        );
+        let mut stmt = db.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params![max as i64], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        let mut msgs = Vec::new();
+        for row in rows {
+            msgs.push(row?);
+        }
+        Ok(msgs)
+    }
+    pub fn mark_delivered(&self, db: &rusqlite::Connection, id: i64) -> crate::Result<()> {
+        let sql = format!("UPDATE {} SET delivered_at = ?1 WHERE id = ?2", self.table);
+        db.execute(&sql, rusqlite::params![now(), id])?;
+        Ok(())
+    }
+    /// Deletes the delivered messages and returns the number of them.
+    pub fn purge(&self, db: &rusqlite::Connection) -> crate::Result<usize> {
+        let sql = format!("DELETE FROM {} WHERE delivered_at IS NOT NULL", self.table);
+        Ok(db.execute(&sql, rusqlite::NO_PARAMS)?)
+    }
+}
+
+/// A relay task, which drains the [Outbox] through the [Producer] with
+/// the publisher confirms and marks the confirmed messages as delivered.
+///
+/// The messages are delivered at least once, as the message published
+/// but not marked yet is published again after the relay restarts.
+///
+/// [Outbox]: struct.Outbox.html
+/// [Producer]: ../produce/struct.Producer.html
+pub struct OutboxRelay {
+    db: rusqlite::Connection,
+    outbox: Outbox,
+    producer: crate::Producer,
+    batch: usize,
+    interval: Duration,
+}
+
+impl OutboxRelay {
+    /// Creates the relay over the database connection and the [Producer],
+    /// which should enable the publisher confirms through
+    /// [ProducerBuilder::confirm].
+    ///
+    /// [Producer]: ../produce/struct.Producer.html
+    /// [ProducerBuilder::confirm]: ../produce/struct.ProducerBuilder.html#method.confirm
+    pub fn new(
+        db: rusqlite::Connection,
+        outbox: Outbox,
+        producer: crate::Producer,
+    ) -> crate::Result<Self> {
+        if !producer.is_confirmed() {
+            return Err(crate::Error::Config(String::from(
+                "outbox relay requires the publisher confirms",
+            )));
+        }
+        outbox.init(&db)?;
+        Ok(Self {
+            db,
+            outbox,
+            producer,
+            batch: DEFAULT_RELAY_BATCH,
+            interval: DEFAULT_RELAY_INTERVAL,
+        })
+    }
+    /// Specify the maximum number of messages relayed at once,
+    /// [DEFAULT_RELAY_BATCH] by default.
+    ///
+    /// [DEFAULT_RELAY_BATCH]: constant.DEFAULT_RELAY_BATCH.html
+    pub fn batch(&mut self, batch: usize) -> &mut Self {
+        self.batch = batch;
+        self
+    }
+    /// Specify the time to wait before polling the empty outbox again,
+    /// [DEFAULT_RELAY_INTERVAL] by default.
+    ///
+    /// [DEFAULT_RELAY_INTERVAL]: constant.DEFAULT_RELAY_INTERVAL.html
+    pub fn interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+    /// Relays the pending messages, up to the batch, and returns the number
+    /// of the delivered ones.
+    pub async fn relay(&mut self) -> crate::Result<usize> {
+        let msgs = self.outbox.pending(&self.db, self.batch)?;
+        let count = msgs.len();
+        for (id, msg) in msgs {
+            self.producer.deliver(msg).await?;
+            self.outbox.mark_delivered(&self.db, id)?;
+        }
+        Ok(count)
+    }
+    /// Relays the messages until it fails, e.g. with [Error::Rejected].
+    ///
+    /// [Error::Rejected]: ../error/enum.Error.html#variant.Rejected
+    pub async fn run(&mut self) -> crate::Result<()> {
+        loop {
+            if self.relay().await? == 0 {
+                self.producer.timer().delay(self.interval).await;
+            }
+        }
+    }
+}
+
+/// Returns the current UNIX time in milliseconds.
+fn now() -> i64 {
+    crate::sqlite::unix_millis(SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn new() {
+        struct Test {
+            data: &'static str,
+            want: bool,
+        }
+        let tests = [
+            Test {
+                data: "outbox",
+                want: true,
+            },
+            Test {
+                data: "order_events_2",
+                want: true,
+            },
+            Test {
+                data: "",
+                want: false,
+            },
+            Test {
+                data: "outbox; DROP TABLE orders",
+                want: false,
+            },
+        ];
+        for t in &tests {
+            assert_eq!(t.want, super::Outbox::new(t.data).is_ok(), "{}", t.data);
+        }
+    }
+    #[test]
+    fn store() {
+        struct Test {
+            data: (&'static [u8], bool),
+            want: usize,
+        }
+        let tests = [
+            Test {
+                data: (b"committed", true),
+                want: 1,
+            },
+            Test {
+                data: (b"rolled back", false),
+                want: 0,
+            },
+        ];
+        for t in &tests {
+            let mut db = rusqlite::Connection::open_in_memory().unwrap();
+            let outbox = super::Outbox::default();
+            outbox.init(&db).unwrap();
+            let tx = db.transaction().unwrap();
+            outbox.store(&tx, t.data.0).unwrap();
+            if t.data.1 {
+                tx.commit().unwrap();
+            } else {
+                tx.rollback().unwrap();
+            }
+            assert_eq!(t.want, outbox.pending(&db, 10).unwrap().len());
+        }
+    }
+    #[test]
+    fn mark_delivered() {
+        let db = rusqlite::Connection::open_in_memory().unwrap();
+        let outbox = super::Outbox::new("events").unwrap();
+        outbox.init(&db).unwrap();
+        let first = outbox.store(&db, b"first").unwrap();
+        let second = outbox.store(&db, b"second").unwrap();
+        assert_eq!(
+            vec![(first, b"first".to_vec()), (second, b"second".to_vec())],
+            outbox.pending(&db, 10).unwrap()
+        );
+        outbox.mark_delivered(&db, first).unwrap();
+        assert_eq!(
+            vec![(second, b"second".to_vec())],
+            outbox.pending(&db, 10).unwrap()
+        );
+        assert_eq!(1, outbox.purge(&db).unwrap());
+    }
+}
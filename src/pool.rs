@@ -0,0 +1,527 @@
+// SPDX-License-Identifier: Apache-2.0 AND MIT
+//! `ConsumerPool` struct
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use futures::future::{self, BoxFuture, Either};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The default backoff before the first restart of the failed consumer.
+pub const DEFAULT_MIN_BACKOFF: Duration = Duration::from_millis(100);
+/// The default maximum backoff, which the backoff doubles up to on each
+/// consecutive restart.
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A [ConsumerPool] worker state.
+///
+/// [ConsumerPool]: struct.ConsumerPool.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WorkerState {
+    /// Building the consumer.
+    Starting,
+    /// Running the consumer.
+    Running,
+    /// Waiting for the backoff to restart the failed consumer.
+    Restarting,
+}
+
+/// An aggregate [ConsumerPool] status, returned by [ConsumerPool::status].
+///
+/// [ConsumerPool]: struct.ConsumerPool.html
+/// [ConsumerPool::status]: struct.ConsumerPool.html#method.status
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PoolStatus {
+    pub starting: usize,
+    pub running: usize,
+    pub restarting: usize,
+    /// The total number of the consumer restarts.
+    pub restarts: u64,
+    /// The latest error of the consumer build or run, across the workers.
+    pub last_error: Option<String>,
+}
+
+impl PoolStatus {
+    /// Returns the number of the workers.
+    pub fn workers(&self) -> usize {
+        self.starting + self.running + self.restarting
+    }
+}
+
+/// A supervisor of the [Consumer]s built by the [ConsumerBuilder], each
+/// run on its own task spawned by [Connection::spawn].  It restarts the
+/// failed consumers with the exponential backoff and scales the number
+/// of them through [resize].
+///
+/// Dropping the pool stops the workers without waiting for them, while
+/// [shutdown] waits for them to close their channels.
+///
+/// [Consumer]: ../consume/struct.Consumer.html
+/// [ConsumerBuilder]: ../consume/struct.ConsumerBuilder.html
+/// [Connection::spawn]: ../client/struct.Connection.html#method.spawn
+/// [resize]: #method.resize
+/// [shutdown]: #method.shutdown
+pub struct ConsumerPool(Pool<crate::ConsumerBuilder>);
+
+/// A supervisor of the [Job]s built by the [Factory].
+///
+/// [Job]: trait.Job.html
+/// [Factory]: trait.Factory.html
+struct Pool<F> {
+    factory: F,
+    backoff: Backoff,
+    workers: Vec<Worker>,
+}
+
+/// A builder of the supervised [Job]s, which also spawns the workers.
+///
+/// [Job]: trait.Job.html
+#[async_trait]
+trait Factory: Clone + Send + Sync + 'static {
+    type Job: Job;
+    async fn build(&self) -> crate::Result<Self::Job>;
+    fn spawn(&self, task: BoxFuture<'static, ()>) -> crate::Result<()>;
+    fn timer(&self) -> Arc<dyn crate::Timer>;
+}
+
+/// A supervised job, e.g. the [Consumer].
+///
+/// [Consumer]: ../consume/struct.Consumer.html
+#[async_trait]
+trait Job: Send + 'static {
+    async fn run(&mut self) -> crate::Result<()>;
+    async fn close(&self) -> crate::Result<()>;
+}
+
+struct Worker {
+    stop: oneshot::Sender<()>,
+    done: oneshot::Receiver<()>,
+    status: Arc<Mutex<WorkerStatus>>,
+}
+
+struct WorkerStatus {
+    state: WorkerState,
+    restarts: u64,
+    last_error: Option<(Instant, String)>,
+}
+
+#[derive(Clone, Copy)]
+struct Backoff {
+    min: Duration,
+    max: Duration,
+}
+
+impl ConsumerPool {
+    /// Creates the empty pool, which spawns the workers on [resize].
+    ///
+    /// [resize]: #method.resize
+    pub fn new(builder: crate::ConsumerBuilder) -> Self {
+        Self(Pool::new(builder))
+    }
+    /// Specify the backoff before the first restart, [DEFAULT_MIN_BACKOFF]
+    /// by default.
+    ///
+    /// [DEFAULT_MIN_BACKOFF]: constant.DEFAULT_MIN_BACKOFF.html
+    pub fn min_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.0.backoff.min = backoff;
+        self
+    }
+    /// Specify the maximum backoff, [DEFAULT_MAX_BACKOFF] by default.
+    /// The consumer, which ran longer than it, restarts with the minimum
+    /// backoff again.
+    ///
+    /// [DEFAULT_MAX_BACKOFF]: constant.DEFAULT_MAX_BACKOFF.html
+    pub fn max_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.0.backoff.max = backoff;
+        self
+    }
+    pub fn len(&self) -> usize {
+        self.0.workers.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.workers.is_empty()
+    }
+    /// Returns the aggregate status of the workers.
+    pub fn status(&self) -> PoolStatus {
+        self.0.status()
+    }
+    /// Spawns the new workers or stops the latest ones, waiting for them,
+    /// to run the `size` number of the consumers.  It fails with
+    /// [Error::NoExecutor] without the [Executor] provided by
+    /// [Client::task_executor].
+    ///
+    /// [Error::NoExecutor]: ../error/enum.Error.html#variant.NoExecutor
+    /// [Executor]: ../runtime/trait.Executor.html
+    /// [Client::task_executor]: ../client/struct.Client.html#method.task_executor
+    pub async fn resize(&mut self, size: usize) -> crate::Result<()> {
+        self.0.resize(size).await
+    }
+    /// Stops all the workers and waits for them.
+    pub async fn shutdown(self) {
+        self.0.shutdown().await
+    }
+}
+
+impl<F: Factory> Pool<F> {
+    fn new(factory: F) -> Self {
+        Self {
+            factory,
+            backoff: Backoff {
+                min: DEFAULT_MIN_BACKOFF,
+                max: DEFAULT_MAX_BACKOFF,
+            },
+            workers: Vec::new(),
+        }
+    }
+    fn status(&self) -> PoolStatus {
+        let mut status = PoolStatus::default();
+        let mut last: Option<Instant> = None;
+        for worker in &self.workers {
+            let worker = worker.status.lock().unwrap();
+            match worker.state {
+                WorkerState::Starting => status.starting += 1,
+                WorkerState::Running => status.running += 1,
+                WorkerState::Restarting => status.restarting += 1,
+            }
+            status.restarts += worker.restarts;
+            if let Some((at, err)) = &worker.last_error {
+                if last < Some(*at) {
+                    last = Some(*at);
+                    status.last_error = Some(err.clone());
+                }
+            }
+        }
+        status
+    }
+    async fn resize(&mut self, size: usize) -> crate::Result<()> {
+        while self.workers.len() < size {
+            self.spawn()?;
+        }
+        let stopped = self.workers.split_off(size);
+        future::join_all(stopped.into_iter().map(Worker::stop)).await;
+        Ok(())
+    }
+    async fn shutdown(mut self) {
+        future::join_all(self.workers.drain(..).map(Worker::stop)).await;
+    }
+    fn spawn(&mut self) -> crate::Result<()> {
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let (done_tx, done_rx) = oneshot::channel();
+        let status = Arc::new(Mutex::new(WorkerStatus {
+            state: WorkerState::Starting,
+            restarts: 0,
+            last_error: None,
+        }));
+        self.factory.spawn(Box::pin(supervise(
+            self.factory.clone(),
+            self.backoff,
+            self.factory.timer(),
+            stop_rx,
+            done_tx,
+            status.clone(),
+        )))?;
+        self.workers.push(Worker {
+            stop: stop_tx,
+            done: done_rx,
+            status,
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Factory for crate::ConsumerBuilder {
+    type Job = crate::Consumer;
+    async fn build(&self) -> crate::Result<crate::Consumer> {
+        crate::ConsumerBuilder::build(self).await
+    }
+    fn spawn(&self, task: BoxFuture<'static, ()>) -> crate::Result<()> {
+        self.connection().spawn(task)
+    }
+    fn timer(&self) -> Arc<dyn crate::Timer> {
+        self.connection().timer()
+    }
+}
+
+#[async_trait]
+impl Job for crate::Consumer {
+    async fn run(&mut self) -> crate::Result<()> {
+        crate::Consumer::run(self).await
+    }
+    async fn close(&self) -> crate::Result<()> {
+        crate::Consumer::close(self).await
+    }
+}
+
+impl Worker {
+    async fn stop(self) {
+        // The worker may have gone already, e.g. with the dropped task.
+        let _ = self.stop.send(());
+        let _ = self.done.await;
+    }
+}
+
+impl WorkerStatus {
+    fn transit(status: &Mutex<Self>, state: WorkerState) {
+        let mut status = status.lock().unwrap();
+        if state == WorkerState::Restarting {
+            status.restarts += 1;
+        }
+        status.state = state;
+    }
+    fn fail(status: &Mutex<Self>, err: crate::Error) {
+        status.lock().unwrap().last_error = Some((Instant::now(), err.to_string()));
+    }
+}
+
+impl Backoff {
+    /// Returns the backoff of the `attempt`th consecutive restart.
+    fn delay(&self, attempt: u32) -> Duration {
+        match self.min.checked_mul(2u32.saturating_pow(attempt)) {
+            Some(delay) if delay < self.max => delay,
+            _ => self.max,
+        }
+    }
+}
+
+/// Builds and runs the job until it's stopped, restarting it with
+/// the backoff once it fails.
+async fn supervise<F: Factory>(
+    factory: F,
+    backoff: Backoff,
+    timer: Arc<dyn crate::Timer>,
+    mut stop: oneshot::Receiver<()>,
+    done: oneshot::Sender<()>,
+    status: Arc<Mutex<WorkerStatus>>,
+) {
+    let mut attempt = 0;
+    loop {
+        WorkerStatus::transit(&status, WorkerState::Starting);
+        let started = Instant::now();
+        let job = match future::select(factory.build(), &mut stop).await {
+            Either::Left((Ok(job), _)) => Some(job),
+            Either::Left((Err(err), _)) => {
+                WorkerStatus::fail(&status, err);
+                None
+            }
+            Either::Right(_) => break,
+        };
+        if let Some(mut job) = job {
+            WorkerStatus::transit(&status, WorkerState::Running);
+            let stopped = match future::select(job.run(), &mut stop).await {
+                Either::Left((Ok(()), _)) => false,
+                Either::Left((Err(err), _)) => {
+                    WorkerStatus::fail(&status, err);
+                    false
+                }
+                Either::Right(_) => true,
+            };
+            // Let the broker requeue the unacknowledged messages.
+            let _ = job.close().await;
+            if stopped {
+                break;
+            }
+            if started.elapsed() > backoff.max {
+                attempt = 0;
+            }
+        }
+        WorkerStatus::transit(&status, WorkerState::Restarting);
+        let delay = backoff.delay(attempt);
+        attempt = attempt.saturating_add(1);
+        if let Either::Right(_) = future::select(timer.delay(delay), &mut stop).await {
+            break;
+        }
+    }
+    let _ = done.send(());
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use futures::executor::block_on;
+    use futures::future::{self, BoxFuture};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[derive(Clone)]
+    struct Stub {
+        executor: futures_executor::ThreadPool,
+        build: fn() -> crate::Result<()>,
+        run: fn() -> crate::Result<()>,
+        closed: Arc<AtomicUsize>,
+    }
+
+    struct StubJob {
+        run: fn() -> crate::Result<()>,
+        closed: Arc<AtomicUsize>,
+    }
+
+    impl Stub {
+        fn new(build: fn() -> crate::Result<()>, run: fn() -> crate::Result<()>) -> Self {
+            Self {
+                executor: futures_executor::ThreadPool::new().unwrap(),
+                build,
+                run,
+                closed: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+        fn pool(&self) -> super::Pool<Self> {
+            let mut pool = super::Pool::new(self.clone());
+            pool.backoff.min = Duration::from_millis(1);
+            pool.backoff.max = Duration::from_millis(10);
+            pool
+        }
+    }
+
+    #[async_trait]
+    impl super::Factory for Stub {
+        type Job = StubJob;
+        async fn build(&self) -> crate::Result<StubJob> {
+            (self.build)()?;
+            Ok(StubJob {
+                run: self.run,
+                closed: self.closed.clone(),
+            })
+        }
+        fn spawn(&self, task: BoxFuture<'static, ()>) -> crate::Result<()> {
+            self.executor.spawn_ok(task);
+            Ok(())
+        }
+        fn timer(&self) -> Arc<dyn crate::Timer> {
+            Arc::new(crate::FuturesTimer)
+        }
+    }
+
+    #[async_trait]
+    impl super::Job for StubJob {
+        async fn run(&mut self) -> crate::Result<()> {
+            (self.run)()?;
+            future::pending().await
+        }
+        async fn close(&self) -> crate::Result<()> {
+            self.closed.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    /// Waits for the pool status to satisfy the condition.
+    fn wait<F>(pool: &super::Pool<Stub>, cond: F) -> super::PoolStatus
+    where
+        F: Fn(&super::PoolStatus) -> bool,
+    {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let status = pool.status();
+            if cond(&status) || Instant::now() > deadline {
+                return status;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn restart() {
+        struct Test {
+            data: Stub,
+            want: (Option<String>, bool),
+        }
+        let tests = [
+            Test {
+                data: Stub::new(|| Err(crate::Error::Timeout), || Ok(())),
+                want: (Some(String::from("timed out")), false),
+            },
+            Test {
+                data: Stub::new(|| Ok(()), || Err(crate::Error::RateLimited)),
+                want: (Some(String::from("rate limited")), true),
+            },
+        ];
+        for t in &tests {
+            let mut pool = t.data.pool();
+            block_on(pool.resize(1)).unwrap();
+            let got = wait(&pool, |status| status.restarts >= 3);
+            assert!(got.restarts >= 3, "{:?}", got);
+            assert_eq!(1, got.workers());
+            assert_eq!(t.want.0, got.last_error);
+            block_on(pool.shutdown());
+            let closed = t.data.closed.load(Ordering::SeqCst);
+            assert_eq!(t.want.1, closed >= 3, "closed={}", closed);
+        }
+    }
+    #[test]
+    fn resize() {
+        struct Test {
+            data: usize,
+            want: usize,
+        }
+        let tests = [
+            Test { data: 3, want: 0 },
+            Test { data: 5, want: 0 },
+            Test { data: 2, want: 3 },
+            Test { data: 2, want: 3 },
+            Test { data: 0, want: 5 },
+        ];
+        let stub = Stub::new(|| Ok(()), || Ok(()));
+        let mut pool = stub.pool();
+        for t in &tests {
+            block_on(pool.resize(t.data)).unwrap();
+            assert_eq!(t.data, pool.workers.len());
+            let got = wait(&pool, |status| status.running == t.data);
+            assert_eq!(t.data, got.running, "{}", t.data);
+            assert_eq!(0, got.restarts);
+            assert_eq!(t.want, stub.closed.load(Ordering::SeqCst), "{}", t.data);
+        }
+    }
+    #[test]
+    fn shutdown() {
+        struct Test {
+            data: usize,
+            want: usize,
+        }
+        let tests = [Test { data: 0, want: 0 }, Test { data: 4, want: 4 }];
+        for t in &tests {
+            let stub = Stub::new(|| Ok(()), || Ok(()));
+            let mut pool = stub.pool();
+            block_on(pool.resize(t.data)).unwrap();
+            wait(&pool, |status| status.running == t.data);
+            block_on(pool.shutdown());
+            assert_eq!(t.want, stub.closed.load(Ordering::SeqCst), "{}", t.data);
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        struct Test {
+            data: u32,
+            want: Duration,
+        }
+        let backoff = super::Backoff {
+            min: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+        };
+        let tests = [
+            Test {
+                data: 0,
+                want: Duration::from_millis(100),
+            },
+            Test {
+                data: 1,
+                want: Duration::from_millis(200),
+            },
+            Test {
+                data: 3,
+                want: Duration::from_millis(800),
+            },
+            Test {
+                data: 4,
+                want: Duration::from_secs(1),
+            },
+            Test {
+                data: u32::MAX,
+                want: Duration::from_secs(1),
+            },
+        ];
+        for t in &tests {
+            assert_eq!(t.want, backoff.delay(t.data), "{}", t.data);
+        }
+    }
+}
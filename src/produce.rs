@@ -1,6 +1,25 @@
 // SPDX-License-Identifier: Apache-2.0 AND MIT
 //! `ProducerBuilder` and `Producer` structs
+use futures::future::{self, BoxFuture, Either};
+use futures::Future;
 use futures_util::stream::StreamExt;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The default consumer tag of the reply queue.
+pub const DEFAULT_CONSUMER_TAG: &str = "producer";
+
+/// The delayed message exchange type, provided by the broker's
+/// `rabbitmq_delayed_message_exchange` plugin.
+pub const DELAYED_MESSAGE_EXCHANGE: &str = "x-delayed-message";
+
+/// The time the delay queue outlives its last message before the broker
+/// deletes it.
+pub const DELAY_QUEUE_GRACE: Duration = Duration::from_secs(60);
+
+/// The default time to wait for the publisher confirm.
+pub const DEFAULT_CONFIRM_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// A [non-consuming] [Producer] builder.
 ///
@@ -16,13 +35,93 @@ pub struct ProducerBuilder {
     queue_opts: lapin::options::QueueDeclareOptions,
     bind_opts: lapin::options::QueueBindOptions,
     field_table: lapin::types::FieldTable,
+    queue_field: lapin::types::FieldTable,
+    max_length_bytes: Option<u64>,
+    verify: bool,
     tx_props: lapin::BasicProperties,
     tx_opts: lapin::options::BasicPublishOptions,
     rx_opts: lapin::options::BasicConsumeOptions,
+    tag: String,
     ack_opts: lapin::options::BasicAckOptions,
     rej_opts: lapin::options::BasicRejectOptions,
     nack_opts: lapin::options::BasicNackOptions,
     peeker: Box<dyn crate::MessagePeek + Send + Sync>,
+    blocked: BlockedPolicy,
+    confirm: Option<Duration>,
+    rpc_timeout: Option<Duration>,
+    delay: DelayMode,
+    limiter: crate::RateLimiter,
+}
+
+/// [Producer::publish_after] and [Producer::publish_at] delay mechanism.
+///
+/// [Producer::publish_after]: struct.Producer.html#method.publish_after
+/// [Producer::publish_at]: struct.Producer.html#method.publish_at
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DelayMode {
+    /// Park the message, with the delay as the per-message expiration, in
+    /// the delay queue declared by the crate, which dead-letters it to
+    /// the producer's exchange and the routing key once it expires.
+    ///
+    /// The broker expires the messages at the head of the queue only, so
+    /// the delays are bucketed by the next power of two milliseconds, one
+    /// queue per bucket, and the message is delivered no later than its
+    /// bucket.
+    #[default]
+    DeadLetter,
+    /// Publish the message with the `x-delay` header to the exchange
+    /// declared as the [DELAYED_MESSAGE_EXCHANGE], which requires the
+    /// broker plugin and the named exchange.
+    ///
+    /// [DELAYED_MESSAGE_EXCHANGE]: constant.DELAYED_MESSAGE_EXCHANGE.html
+    Plugin,
+}
+
+/// [Producer] policy while the broker blocks the connection.
+///
+/// [Producer]: struct.Producer.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockedPolicy {
+    /// Fail fast with [Error::Blocked].
+    ///
+    /// [Error::Blocked]: ../error/enum.Error.html#variant.Blocked
+    Fail,
+    /// Wait until the broker unblocks the connection.
+    Wait,
+    /// Buffer up to the specified number of messages and fail with
+    /// [Error::Blocked] once the buffer is full.  The buffered messages
+    /// are published, in order, by the next [Producer::publish] or
+    /// [Producer::flush] call after the connection is unblocked.
+    ///
+    /// [Producer::rpc] waits for the connection to be unblocked instead,
+    /// as the reply can't be buffered.
+    ///
+    /// [Error::Blocked]: ../error/enum.Error.html#variant.Blocked
+    /// [Producer::publish]: struct.Producer.html#method.publish
+    /// [Producer::flush]: struct.Producer.html#method.flush
+    /// [Producer::rpc]: struct.Producer.html#method.rpc
+    Buffer(usize),
+}
+
+/// An action on the message published over the blocked connection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BlockedAction {
+    Fail,
+    Wait,
+    Buffer,
+}
+
+impl BlockedPolicy {
+    /// Returns the action on the message published over the blocked
+    /// connection, with the `buffered` number of messages in the buffer.
+    fn action(self, buffered: usize) -> BlockedAction {
+        match self {
+            Self::Fail => BlockedAction::Fail,
+            Self::Wait => BlockedAction::Wait,
+            Self::Buffer(limit) if buffered < limit => BlockedAction::Buffer,
+            Self::Buffer(_) => BlockedAction::Fail,
+        }
+    }
 }
 
 impl ProducerBuilder {
@@ -36,13 +135,22 @@ impl ProducerBuilder {
             queue_opts: lapin::options::QueueDeclareOptions::default(),
             bind_opts: lapin::options::QueueBindOptions::default(),
             field_table: lapin::types::FieldTable::default(),
+            queue_field: lapin::types::FieldTable::default(),
+            max_length_bytes: None,
+            verify: false,
             tx_props: lapin::BasicProperties::default(),
             tx_opts: lapin::options::BasicPublishOptions::default(),
             rx_opts: lapin::options::BasicConsumeOptions::default(),
+            tag: String::from(DEFAULT_CONSUMER_TAG),
             ack_opts: lapin::options::BasicAckOptions::default(),
             rej_opts: lapin::options::BasicRejectOptions::default(),
             nack_opts: lapin::options::BasicNackOptions::default(),
             peeker: Box::new(crate::message::NoopPeeker {}),
+            blocked: BlockedPolicy::Wait,
+            confirm: None,
+            rpc_timeout: None,
+            delay: DelayMode::default(),
+            limiter: crate::RateLimiter::default(),
         }
     }
     /// Specify the exchange name.
@@ -55,6 +163,23 @@ impl ProducerBuilder {
         self.queue = queue.to_string();
         self
     }
+    /// Verify the exchange and the destination queue with the passive
+    /// declarations, instead of declaring the exchange, and fail with
+    /// [Error::NotFound] in case either of them is missing.
+    ///
+    /// [Error::NotFound]: ../error/enum.Error.html#variant.NotFound
+    pub fn verify(&mut self, verify: bool) -> &mut Self {
+        self.verify = verify;
+        self
+    }
+    /// Specify the consumer tag of the reply queue, [DEFAULT_CONSUMER_TAG]
+    /// by default.
+    ///
+    /// [DEFAULT_CONSUMER_TAG]: constant.DEFAULT_CONSUMER_TAG.html
+    pub fn consumer_tag(&mut self, tag: &str) -> &mut Self {
+        self.tag = tag.to_string();
+        self
+    }
     /// Use the provided [MessagePeek] trait object.
     ///
     /// [MessagePeek]: ../message/trait.MessagePeek.html
@@ -62,21 +187,203 @@ impl ProducerBuilder {
         self.peeker = peeker;
         self
     }
+    /// Specify the per-message expiration, after which the broker drops
+    /// or dead-letters the message.
+    pub fn expiration(&mut self, ttl: Duration) -> &mut Self {
+        let ttl = ttl.as_millis().to_string();
+        self.tx_props = self.tx_props.clone().with_expiration(ttl.into());
+        self
+    }
+    /// Specify the reply queue message TTL through the `x-message-ttl`
+    /// argument, e.g. to drop the late [Producer::rpc] replies.
+    ///
+    /// [Producer::rpc]: struct.Producer.html#method.rpc
+    pub fn message_ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.queue_argument("x-message-ttl", crate::consume::millis(ttl))
+    }
+    /// Specify the reply queue TTL through the `x-expires` argument, after
+    /// which the unused queue is deleted.
+    pub fn expires(&mut self, ttl: Duration) -> &mut Self {
+        self.queue_argument("x-expires", crate::consume::millis(ttl))
+    }
+    /// Specify the maximum number of messages in the reply queue through
+    /// the `x-max-length` argument.
+    pub fn max_length(&mut self, len: u32) -> &mut Self {
+        let len = lapin::types::AMQPValue::LongLongInt(i64::from(len));
+        self.queue_argument("x-max-length", len)
+    }
+    /// Specify the maximum total size of the message bodies in the reply
+    /// queue through the `x-max-length-bytes` argument.  [build] fails with
+    /// [Error::Config] in case it's out of the argument range.
+    ///
+    /// [build]: #method.build
+    /// [Error::Config]: ../error/enum.Error.html#variant.Config
+    pub fn max_length_bytes(&mut self, bytes: u64) -> &mut Self {
+        self.max_length_bytes = Some(bytes);
+        self
+    }
+    /// Specify the reply queue [Overflow] behavior through the `x-overflow`
+    /// argument.
+    ///
+    /// [Overflow]: ../consume/enum.Overflow.html
+    pub fn overflow(&mut self, overflow: crate::Overflow) -> &mut Self {
+        self.queue_argument("x-overflow", overflow.value())
+    }
+    fn queue_argument(&mut self, key: &str, val: lapin::types::AMQPValue) -> &mut Self {
+        self.queue_field.insert(key.into(), val);
+        self
+    }
+    /// Specify the message priority, delivered first by the queue declared
+    /// with the maximum priority.
+    pub fn priority(&mut self, priority: u8) -> &mut Self {
+        self.tx_props = self.tx_props.clone().with_priority(priority);
+        self
+    }
+    /// Enable the publisher confirms, so that [Producer::publish] and
+    /// [Producer::rpc] wait for the broker to confirm each message and
+    /// fail with [Error::Rejected] in case the broker returns it, e.g.
+    /// the unroutable [mandatory] one.
+    ///
+    /// lapin doesn't resolve the confirmation of the nacked message, so
+    /// the message not confirmed within [DEFAULT_CONFIRM_TIMEOUT] fails
+    /// with [Error::Unconfirmed], e.g. in case of the `reject-publish`
+    /// queue overflow.  Use [confirm_timeout] to adjust it.
+    ///
+    /// [Producer::publish]: struct.Producer.html#method.publish
+    /// [Producer::rpc]: struct.Producer.html#method.rpc
+    /// [Error::Rejected]: ../error/enum.Error.html#variant.Rejected
+    /// [mandatory]: #method.mandatory
+    /// [Error::Unconfirmed]: ../error/enum.Error.html#variant.Unconfirmed
+    /// [DEFAULT_CONFIRM_TIMEOUT]: constant.DEFAULT_CONFIRM_TIMEOUT.html
+    /// [confirm_timeout]: #method.confirm_timeout
+    pub fn confirm(&mut self, confirm: bool) -> &mut Self {
+        self.confirm = if confirm {
+            Some(self.confirm.unwrap_or(DEFAULT_CONFIRM_TIMEOUT))
+        } else {
+            None
+        };
+        self
+    }
+    /// Enable the publisher confirms with the specified timeout.
+    pub fn confirm_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.confirm = Some(timeout);
+        self
+    }
+    /// Publish the messages with the `mandatory` flag, so that the broker
+    /// returns the unroutable ones instead of dropping them.  They fail
+    /// with [Error::Rejected] with the publisher [confirm]s.
+    ///
+    /// [Error::Rejected]: ../error/enum.Error.html#variant.Rejected
+    /// [confirm]: #method.confirm
+    pub fn mandatory(&mut self, mandatory: bool) -> &mut Self {
+        self.tx_opts.mandatory = mandatory;
+        self
+    }
+    /// Specify the time to wait for the [Producer::rpc] reply, after which
+    /// it fails with [Error::Timeout].  It waits forever by default.
+    ///
+    /// [Producer::rpc]: struct.Producer.html#method.rpc
+    /// [Error::Timeout]: ../error/enum.Error.html#variant.Timeout
+    pub fn rpc_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.rpc_timeout = Some(timeout);
+        self
+    }
+    /// Specify the [DelayMode], which is [DelayMode::DeadLetter] by default.
+    ///
+    /// [DelayMode]: enum.DelayMode.html
+    /// [DelayMode::DeadLetter]: enum.DelayMode.html#variant.DeadLetter
+    pub fn delay_mode(&mut self, mode: DelayMode) -> &mut Self {
+        self.delay = mode;
+        self
+    }
+    /// Limit the number of the published messages per second, including
+    /// the [Producer::rpc] requests and the [Producer::flush]ed messages.
+    /// Zero removes the limit.
+    ///
+    /// [Producer::rpc]: struct.Producer.html#method.rpc
+    /// [Producer::flush]: struct.Producer.html#method.flush
+    pub fn message_rate(&mut self, per_sec: u32) -> &mut Self {
+        self.limiter.message_rate(per_sec);
+        self
+    }
+    /// Limit the number of the published message bytes per second, the same
+    /// way as [message_rate].  Zero removes the limit.
+    ///
+    /// [message_rate]: #method.message_rate
+    pub fn byte_rate(&mut self, per_sec: u64) -> &mut Self {
+        self.limiter.byte_rate(per_sec);
+        self
+    }
+    /// Specify the [RateLimitPolicy], which is [RateLimitPolicy::Wait]
+    /// by default.
+    ///
+    /// [RateLimitPolicy]: ../limit/enum.RateLimitPolicy.html
+    /// [RateLimitPolicy::Wait]: ../limit/enum.RateLimitPolicy.html#variant.Wait
+    pub fn rate_limit_policy(&mut self, policy: crate::RateLimitPolicy) -> &mut Self {
+        self.limiter.policy(policy);
+        self
+    }
+    /// Specify the [BlockedPolicy], which is [BlockedPolicy::Wait]
+    /// by default.
+    ///
+    /// [BlockedPolicy]: enum.BlockedPolicy.html
+    /// [BlockedPolicy::Wait]: enum.BlockedPolicy.html#variant.Wait
+    pub fn blocked_policy(&mut self, policy: BlockedPolicy) -> &mut Self {
+        self.blocked = policy;
+        self
+    }
     pub async fn build(&self) -> crate::Result<Producer> {
+        let (kind, ex_field) = match self.delay {
+            DelayMode::DeadLetter => (self.kind.clone(), self.field_table.clone()),
+            DelayMode::Plugin => {
+                if self.ex == crate::DEFAULT_EXCHANGE {
+                    return Err(crate::Error::Config(String::from(
+                        "delayed message exchange requires the named exchange",
+                    )));
+                }
+                let mut ex_field = self.field_table.clone();
+                ex_field.insert(
+                    "x-delayed-type".into(),
+                    lapin::types::AMQPValue::LongString(kind_name(&self.kind).into()),
+                );
+                let kind = lapin::ExchangeKind::Custom(String::from(DELAYED_MESSAGE_EXCHANGE));
+                (kind, ex_field)
+            }
+        };
+        if self.verify && !self.queue.is_empty() {
+            self.conn.inspect_queue(&self.queue).await?;
+        }
         let tx = self.conn.channel().await?;
+        if self.confirm.is_some() {
+            tx.confirm_select(lapin::options::ConfirmSelectOptions::default())
+                .await
+                .map_err(crate::Error::from)?;
+        }
         let queue_opts = lapin::options::QueueDeclareOptions {
             exclusive: true,
             auto_delete: true,
             ..self.queue_opts.clone()
         };
+        let mut queue_field = self.field_table.clone();
+        for (key, val) in self.queue_field.inner() {
+            queue_field.insert(key.clone(), val.clone());
+        }
+        if let Some(bytes) = self.max_length_bytes {
+            queue_field.insert(
+                "x-max-length-bytes".into(),
+                crate::consume::max_length_bytes(bytes)?,
+            );
+        }
         let opts = crate::client::QueueOptions {
-            kind: self.kind.clone(),
+            kind,
             ex_opts: self.ex_opts.clone(),
-            ex_field: self.field_table.clone(),
+            ex_field,
             queue_opts,
-            queue_field: self.field_table.clone(),
+            queue_field,
             bind_opts: self.bind_opts.clone(),
             bind_field: self.field_table.clone(),
+            routing_key: None,
+            verify: self.verify,
         };
         let (rx, q) = self
             .conn
@@ -85,15 +392,17 @@ impl ProducerBuilder {
         let consume = rx
             .basic_consume(
                 q.name().as_str(),
-                "producer",
+                &self.tag,
                 self.rx_opts.clone(),
                 self.field_table.clone(),
             )
             .await
             .map_err(crate::Error::from)?;
         Ok(Producer {
+            conn: self.conn.clone(),
             tx,
             rx,
+            declare: None,
             consume,
             ex: self.ex.clone(),
             queue: self.queue.clone(),
@@ -104,16 +413,88 @@ impl ProducerBuilder {
             rej_opts: self.rej_opts.clone(),
             nack_opts: self.nack_opts.clone(),
             peeker: self.peeker.clone(),
+            blocked: self.blocked,
+            backlog: VecDeque::new(),
+            confirm: self.confirm,
+            rpc_timeout: self.rpc_timeout,
+            rpc_id: 0,
+            delay: self.delay,
+            limiter: if self.limiter.is_limited() {
+                let mut limiter = self.limiter.clone();
+                limiter.timer(self.conn.timer());
+                Some(limiter)
+            } else {
+                None
+            },
         })
     }
 }
 
+/// Returns the exchange type name, e.g. for the `x-delayed-type` argument.
+fn kind_name(kind: &lapin::ExchangeKind) -> &str {
+    match kind {
+        lapin::ExchangeKind::Custom(kind) => kind,
+        lapin::ExchangeKind::Direct => "direct",
+        lapin::ExchangeKind::Fanout => "fanout",
+        lapin::ExchangeKind::Headers => "headers",
+        lapin::ExchangeKind::Topic => "topic",
+    }
+}
+
+/// The delay queue name prefix, out of the broker reserved `amq.` one.
+const DELAY_QUEUE_PREFIX: &str = "async-mq.delay";
+
+/// Returns the name and the arguments of the delay queue of the delay
+/// bucket, which dead-letters the expired messages to the exchange and
+/// the routing key.
+fn delay_queue(ex: &str, queue: &str, delay: Duration) -> (String, lapin::types::FieldTable) {
+    let bucket = delay_bucket(delay);
+    let expires = (bucket + DELAY_QUEUE_GRACE).as_millis() as i64;
+    let name = if ex.is_empty() {
+        format!("{}.{}.{}", DELAY_QUEUE_PREFIX, queue, bucket.as_millis())
+    } else {
+        format!(
+            "{}.{}.{}.{}",
+            DELAY_QUEUE_PREFIX,
+            ex,
+            queue,
+            bucket.as_millis()
+        )
+    };
+    let mut args = lapin::types::FieldTable::default();
+    args.insert(
+        "x-expires".into(),
+        lapin::types::AMQPValue::LongLongInt(expires),
+    );
+    args.insert(
+        "x-dead-letter-exchange".into(),
+        lapin::types::AMQPValue::LongString(ex.into()),
+    );
+    args.insert(
+        "x-dead-letter-routing-key".into(),
+        lapin::types::AMQPValue::LongString(queue.into()),
+    );
+    (name, args)
+}
+
+/// Returns the delay bucket, which is the delay rounded up to the next
+/// power of two milliseconds.
+fn delay_bucket(delay: Duration) -> Duration {
+    let millis = delay.as_millis().max(1);
+    match u64::try_from(millis) {
+        Ok(millis) => Duration::from_millis(millis.checked_next_power_of_two().unwrap_or(millis)),
+        Err(_) => delay,
+    }
+}
+
 /// A zero-cost message producer over [lapin::Channel].
 ///
 /// [lapin::Channel]: https://docs.rs/lapin/latest/lapin/struct.Channel.html
 pub struct Producer {
+    conn: crate::Connection,
     tx: lapin::Channel,
     rx: lapin::Channel,
+    declare: Option<lapin::Channel>,
     consume: lapin::Consumer,
     ex: String,
     queue: String,
@@ -124,6 +505,13 @@ pub struct Producer {
     rej_opts: lapin::options::BasicRejectOptions,
     nack_opts: lapin::options::BasicNackOptions,
     peeker: Box<dyn crate::MessagePeek + Send>,
+    blocked: BlockedPolicy,
+    backlog: VecDeque<(Vec<u8>, lapin::BasicProperties)>,
+    confirm: Option<Duration>,
+    rpc_timeout: Option<Duration>,
+    rpc_id: u64,
+    delay: DelayMode,
+    limiter: Option<crate::RateLimiter>,
 }
 
 impl Producer {
@@ -135,33 +523,271 @@ impl Producer {
         self
     }
     pub async fn publish(&mut self, msg: Vec<u8>) -> crate::Result<()> {
+        let props = self.tx_props.clone();
+        self.publish_with(msg, props).await
+    }
+    /// Publish the message with the specified priority, instead of the one
+    /// specified by [ProducerBuilder::priority].
+    ///
+    /// [ProducerBuilder::priority]: struct.ProducerBuilder.html#method.priority
+    pub async fn publish_with_priority(&mut self, msg: Vec<u8>, priority: u8) -> crate::Result<()> {
+        let props = self.tx_props.clone().with_priority(priority);
+        self.publish_with(msg, props).await
+    }
+    async fn publish_with(
+        &mut self,
+        msg: Vec<u8>,
+        props: lapin::BasicProperties,
+    ) -> crate::Result<()> {
+        if self.conn.is_blocked() {
+            match self.blocked.action(self.backlog.len()) {
+                BlockedAction::Fail => return Err(crate::Error::Blocked),
+                BlockedAction::Wait => self.conn.unblocked().await,
+                BlockedAction::Buffer => {
+                    self.backlog.push_back((msg, props));
+                    return Ok(());
+                }
+            }
+        }
+        self.flush().await?;
+        self.throttle(msg.len()).await?;
+        self.send(msg, props).await
+    }
+    /// Publish the messages buffered by [BlockedPolicy::Buffer], unless
+    /// the connection is still blocked.
+    ///
+    /// [BlockedPolicy::Buffer]: enum.BlockedPolicy.html#variant.Buffer
+    pub async fn flush(&mut self) -> crate::Result<()> {
+        while !self.conn.is_blocked() {
+            let len = match self.backlog.front() {
+                None => break,
+                Some((msg, _)) => msg.len(),
+            };
+            // Keeps the message buffered in case of the rate limit.
+            self.throttle(len).await?;
+            if let Some((msg, props)) = self.backlog.pop_front() {
+                self.send(msg, props).await?;
+            }
+        }
+        Ok(())
+    }
+    /// Returns the number of messages buffered by [BlockedPolicy::Buffer].
+    ///
+    /// [BlockedPolicy::Buffer]: enum.BlockedPolicy.html#variant.Buffer
+    pub fn buffered(&self) -> usize {
+        self.backlog.len()
+    }
+    /// Publishes the message right away, without [BlockedPolicy::Buffer],
+    /// e.g. for the outbox relay.
+    ///
+    /// [BlockedPolicy::Buffer]: enum.BlockedPolicy.html#variant.Buffer
+    #[cfg(feature = "outbox")]
+    pub(crate) async fn deliver(&mut self, msg: Vec<u8>) -> crate::Result<()> {
+        self.unblocked().await?;
+        self.throttle(msg.len()).await?;
+        let props = self.tx_props.clone();
+        self.send(msg, props).await
+    }
+    #[cfg(feature = "outbox")]
+    pub(crate) fn timer(&self) -> std::sync::Arc<dyn crate::Timer> {
+        self.conn.timer()
+    }
+    /// Returns `true` in case the publisher confirms are enabled.
+    #[cfg(feature = "outbox")]
+    pub(crate) fn is_confirmed(&self) -> bool {
+        self.confirm.is_some()
+    }
+    /// Waits for the connection to be unblocked, or fails with
+    /// [Error::Blocked] in case of [BlockedPolicy::Fail].
+    ///
+    /// [Error::Blocked]: ../error/enum.Error.html#variant.Blocked
+    /// [BlockedPolicy::Fail]: enum.BlockedPolicy.html#variant.Fail
+    async fn unblocked(&self) -> crate::Result<()> {
+        if self.conn.is_blocked() {
+            match self.blocked {
+                BlockedPolicy::Fail => return Err(crate::Error::Blocked),
+                BlockedPolicy::Wait | BlockedPolicy::Buffer(_) => self.conn.unblocked().await,
+            }
+        }
+        Ok(())
+    }
+    /// Applies the rate limit, if any, to the message of `len` bytes.
+    async fn throttle(&mut self, len: usize) -> crate::Result<()> {
+        match self.limiter.as_mut() {
+            Some(limiter) => limiter.acquire(len).await,
+            None => Ok(()),
+        }
+    }
+    /// Returns the exchange, the routing key, the options and the properties
+    /// of the published messages.
+    pub(crate) fn target(
+        &self,
+    ) -> (
+        &str,
+        &str,
+        lapin::options::BasicPublishOptions,
+        lapin::BasicProperties,
+    ) {
+        (
+            &self.ex,
+            &self.queue,
+            self.tx_opts.clone(),
+            self.tx_props.clone(),
+        )
+    }
+    async fn send(&mut self, msg: Vec<u8>, props: lapin::BasicProperties) -> crate::Result<()> {
         self.tx
-            .basic_publish(
-                &self.ex,
-                &self.queue,
-                self.tx_opts.clone(),
-                msg,
-                self.tx_props.clone(),
-            )
+            .basic_publish(&self.ex, &self.queue, self.tx_opts.clone(), msg, props)
             .await
             .map_err(crate::Error::from)?;
+        crate::metrics::published(&self.ex, &self.queue);
+        self.confirmed().await
+    }
+    /// Waits for the publisher confirm, in case it's enabled.
+    async fn confirmed(&mut self) -> crate::Result<()> {
+        let timeout = match self.confirm {
+            None => return Ok(()),
+            Some(timeout) => self.conn.delay(timeout),
+        };
+        match confirm(self.tx.wait_for_confirms(), timeout).await? {
+            0 => {
+                crate::metrics::confirmed(&self.ex, &self.queue);
+                Ok(())
+            }
+            returned => {
+                crate::metrics::returned(&self.ex, &self.queue, returned);
+                Err(crate::Error::Rejected)
+            }
+        }
+    }
+    /// Publish the message to be delivered after the specified delay,
+    /// with the [DelayMode] specified by [ProducerBuilder::delay_mode].
+    ///
+    /// The message is not buffered by [BlockedPolicy::Buffer], but waits
+    /// for the connection to be unblocked, as [Producer::rpc] does.
+    ///
+    /// [DelayMode]: enum.DelayMode.html
+    /// [ProducerBuilder::delay_mode]: struct.ProducerBuilder.html#method.delay_mode
+    /// [BlockedPolicy::Buffer]: enum.BlockedPolicy.html#variant.Buffer
+    /// [Producer::rpc]: struct.Producer.html#method.rpc
+    pub async fn publish_after(&mut self, msg: Vec<u8>, delay: Duration) -> crate::Result<()> {
+        if delay == Duration::default() {
+            return self.publish(msg).await;
+        }
+        self.unblocked().await?;
+        self.throttle(msg.len()).await?;
+        match self.delay {
+            DelayMode::DeadLetter => {
+                // Redeclares the queue to postpone its expiration.
+                let (queue, args) = delay_queue(&self.ex, &self.queue, delay);
+                self.declare_delay_queue(&queue, args).await?;
+                let expiration = delay.as_millis().to_string();
+                self.tx
+                    .basic_publish(
+                        crate::DEFAULT_EXCHANGE,
+                        &queue,
+                        self.tx_opts.clone(),
+                        msg,
+                        self.tx_props.clone().with_expiration(expiration.into()),
+                    )
+                    .await
+                    .map_err(crate::Error::from)?;
+            }
+            DelayMode::Plugin => {
+                let mut headers = self.tx_props.headers().clone().unwrap_or_default();
+                headers.insert(
+                    "x-delay".into(),
+                    lapin::types::AMQPValue::LongLongInt(delay.as_millis() as i64),
+                );
+                self.tx
+                    .basic_publish(
+                        &self.ex,
+                        &self.queue,
+                        self.tx_opts.clone(),
+                        msg,
+                        self.tx_props.clone().with_headers(headers),
+                    )
+                    .await
+                    .map_err(crate::Error::from)?;
+            }
+        }
+        crate::metrics::published(&self.ex, &self.queue);
+        self.confirmed().await
+    }
+    /// Declares the delay queue over the dedicated channel, so that
+    /// the declaration error doesn't close the publishing channel.
+    async fn declare_delay_queue(
+        &mut self,
+        queue: &str,
+        args: lapin::types::FieldTable,
+    ) -> crate::Result<()> {
+        let ch = match self.declare.take() {
+            Some(ch) if ch.status().is_connected() => ch,
+            _ => self.conn.channel().await?,
+        };
+        let opts = lapin::options::QueueDeclareOptions {
+            durable: true,
+            ..lapin::options::QueueDeclareOptions::default()
+        };
+        ch.queue_declare(queue, opts, args)
+            .await
+            .map_err(crate::Error::from)?;
+        self.declare = Some(ch);
         Ok(())
     }
+    /// Publish the message to be delivered at the specified time, or
+    /// right away in case it's already passed.
+    pub async fn publish_at(&mut self, msg: Vec<u8>, at: SystemTime) -> crate::Result<()> {
+        let delay = at.duration_since(SystemTime::now()).unwrap_or_default();
+        self.publish_after(msg, delay).await
+    }
+    /// Sends the request and waits for the reply with the same correlation
+    /// ID, or fails with [Error::Timeout] after [ProducerBuilder::rpc_timeout].
+    ///
+    /// [Error::Timeout]: ../error/enum.Error.html#variant.Timeout
+    /// [ProducerBuilder::rpc_timeout]: struct.ProducerBuilder.html#method.rpc_timeout
     pub async fn rpc(&mut self, msg: Vec<u8>) -> crate::Result<Vec<u8>> {
+        let start = Instant::now();
+        let resp = match self.rpc_timeout {
+            None => self.call(msg).await,
+            Some(timeout) => {
+                let timeout = self.conn.delay(timeout);
+                match future::select(Box::pin(self.call(msg)), timeout).await {
+                    Either::Left((resp, _)) => resp,
+                    Either::Right(_) => Err(crate::Error::Timeout),
+                }
+            }
+        };
+        if resp.is_ok() {
+            crate::metrics::rpc(&self.ex, &self.queue, start.elapsed());
+        }
+        resp
+    }
+    async fn call(&mut self, msg: Vec<u8>) -> crate::Result<Vec<u8>> {
+        self.unblocked().await?;
+        self.throttle(msg.len()).await?;
+        self.rpc_id += 1;
+        let id = self.rpc_id.to_string();
+        let props = self
+            .rx_props
+            .clone()
+            .with_correlation_id(id.as_str().into());
         self.tx
-            .basic_publish(
-                &self.ex,
-                &self.queue,
-                self.tx_opts.clone(),
-                msg,
-                self.rx_props.clone(),
-            )
+            .basic_publish(&self.ex, &self.queue, self.tx_opts.clone(), msg, props)
             .await
             .map_err(crate::Error::from)?;
-        if let Some(msg) = self.consume.next().await {
-            match msg {
-                Ok(msg) => return self.recv(&crate::Message::new(msg)).await,
-                Err(err) => return Err(crate::Error::from(err)),
+        crate::metrics::published(&self.ex, &self.queue);
+        self.confirmed().await?;
+        while let Some(msg) = self.consume.next().await {
+            let msg = crate::Message::new(msg.map_err(crate::Error::from)?);
+            match msg.correlation_id() {
+                // The stale reply of the timed out request.
+                Some(got) if got != id => self
+                    .rx
+                    .basic_ack(msg.delivery_tag(), self.ack_opts.clone())
+                    .await
+                    .map_err(crate::Error::from)?,
+                _ => return self.recv(&msg).await,
             }
         }
         Ok(vec![])
@@ -190,6 +816,219 @@ impl Producer {
                     .map_err(crate::Error::from)?;
                 Ok(vec![])
             }
+            Err(crate::MessageError::Requeue) => {
+                let opts = lapin::options::BasicNackOptions {
+                    requeue: true,
+                    ..self.nack_opts.clone()
+                };
+                self.rx
+                    .basic_nack(msg.delivery_tag(), opts)
+                    .await
+                    .map_err(crate::Error::from)?;
+                Ok(vec![])
+            }
+        }
+    }
+}
+
+/// Waits for the publisher confirms and returns the number of the returned
+/// messages, or fails with [Error::Unconfirmed] after the timeout, e.g.
+/// for the nacked message, which lapin never confirms.
+///
+/// [Error::Unconfirmed]: ../error/enum.Error.html#variant.Unconfirmed
+async fn confirm<F>(confirms: F, timeout: BoxFuture<'static, ()>) -> crate::Result<usize>
+where
+    F: Future<Output = lapin::Result<Vec<lapin::message::BasicReturnMessage>>> + Unpin,
+{
+    match future::select(confirms, timeout).await {
+        Either::Left((Ok(returned), _)) => Ok(returned.len()),
+        Either::Left((Err(err), _)) => Err(crate::Error::from(err)),
+        Either::Right(_) => Err(crate::Error::Unconfirmed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    #[test]
+    fn blocked_action() {
+        use super::{BlockedAction, BlockedPolicy};
+        struct Test {
+            data: (BlockedPolicy, usize),
+            want: BlockedAction,
+        }
+        let tests = [
+            Test {
+                data: (BlockedPolicy::Fail, 0),
+                want: BlockedAction::Fail,
+            },
+            Test {
+                data: (BlockedPolicy::Wait, 0),
+                want: BlockedAction::Wait,
+            },
+            Test {
+                data: (BlockedPolicy::Wait, 10),
+                want: BlockedAction::Wait,
+            },
+            Test {
+                data: (BlockedPolicy::Buffer(2), 0),
+                want: BlockedAction::Buffer,
+            },
+            Test {
+                data: (BlockedPolicy::Buffer(2), 1),
+                want: BlockedAction::Buffer,
+            },
+            Test {
+                data: (BlockedPolicy::Buffer(2), 2),
+                want: BlockedAction::Fail,
+            },
+            Test {
+                data: (BlockedPolicy::Buffer(0), 0),
+                want: BlockedAction::Fail,
+            },
+        ];
+        for t in &tests {
+            let (policy, buffered) = t.data;
+            assert_eq!(t.want, policy.action(buffered), "{:?}", t.data);
+        }
+    }
+
+    #[test]
+    fn delay_queue() {
+        struct Test {
+            name: &'static str,
+            ex: &'static str,
+            queue: &'static str,
+            delay: Duration,
+            want: (&'static str, i64),
+        }
+        let tests = [
+            Test {
+                name: "default exchange",
+                ex: "",
+                queue: "reminder",
+                delay: Duration::from_secs(5),
+                want: ("async-mq.delay.reminder.8192", 68_192),
+            },
+            Test {
+                name: "named exchange",
+                ex: "jobs",
+                queue: "retry",
+                delay: Duration::from_millis(1_500),
+                want: ("async-mq.delay.jobs.retry.2048", 62_048),
+            },
+            Test {
+                name: "same bucket",
+                ex: "jobs",
+                queue: "retry",
+                delay: Duration::from_millis(1_025),
+                want: ("async-mq.delay.jobs.retry.2048", 62_048),
+            },
+        ];
+        for t in &tests {
+            let (name, args) = super::delay_queue(t.ex, t.queue, t.delay);
+            let args = args.inner();
+            assert_eq!(t.want.0, name, "{}", t.name);
+            assert_eq!(None, args.get("x-message-ttl"), "{}", t.name);
+            assert_eq!(
+                Some(&lapin::types::AMQPValue::LongLongInt(t.want.1)),
+                args.get("x-expires"),
+                "{}",
+                t.name
+            );
+            assert_eq!(
+                Some(&lapin::types::AMQPValue::LongString(t.ex.into())),
+                args.get("x-dead-letter-exchange"),
+                "{}",
+                t.name
+            );
+            assert_eq!(
+                Some(&lapin::types::AMQPValue::LongString(t.queue.into())),
+                args.get("x-dead-letter-routing-key"),
+                "{}",
+                t.name
+            );
+        }
+    }
+    #[test]
+    fn delay_bucket() {
+        struct Test {
+            data: Duration,
+            want: Duration,
+        }
+        let tests = [
+            Test {
+                data: Duration::from_micros(10),
+                want: Duration::from_millis(1),
+            },
+            Test {
+                data: Duration::from_millis(1),
+                want: Duration::from_millis(1),
+            },
+            Test {
+                data: Duration::from_millis(1_000),
+                want: Duration::from_millis(1_024),
+            },
+            Test {
+                data: Duration::from_millis(1_024),
+                want: Duration::from_millis(1_024),
+            },
+            Test {
+                data: Duration::from_secs(3_600),
+                want: Duration::from_millis(4_194_304),
+            },
+        ];
+        for t in &tests {
+            assert_eq!(t.want, super::delay_bucket(t.data), "{:?}", t.data);
+        }
+    }
+    #[test]
+    fn confirm() {
+        use futures::executor::block_on;
+        use futures::future::{self, BoxFuture};
+        use lapin::message::{BasicReturnMessage, Delivery};
+        fn returned() -> BasicReturnMessage {
+            BasicReturnMessage {
+                delivery: Delivery {
+                    delivery_tag: 1,
+                    exchange: "".into(),
+                    routing_key: "orders".into(),
+                    redelivered: false,
+                    properties: lapin::BasicProperties::default(),
+                    data: Vec::new(),
+                },
+                reply_code: 312,
+                reply_text: "NO_ROUTE".into(),
+            }
+        }
+        struct Test {
+            data: Option<Vec<BasicReturnMessage>>,
+            want: crate::Result<usize>,
+        }
+        let tests = [
+            Test {
+                data: Some(Vec::new()),
+                want: Ok(0),
+            },
+            Test {
+                data: Some(vec![returned()]),
+                want: Ok(1),
+            },
+            // lapin never resolves the nacked message's confirm.
+            Test {
+                data: None,
+                want: Err(crate::Error::Unconfirmed),
+            },
+        ];
+        for t in &tests {
+            let confirms: BoxFuture<'static, lapin::Result<_>> = match &t.data {
+                Some(returned) => Box::pin(future::ready(Ok(returned.clone()))),
+                None => Box::pin(future::pending()),
+            };
+            let timeout = Box::pin(futures_timer::Delay::new(Duration::from_millis(10)));
+            let got = block_on(super::confirm(confirms, timeout));
+            assert_eq!(t.want, got, "{:?}", t.data);
         }
     }
 }
@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: Apache-2.0 AND MIT
+//! `PullerBuilder` and `Puller` structs
+
+/// A [non-consuming] [Puller] builder.
+///
+/// [Puller]: struct.Puller.html
+/// [non-consuming]: https://doc.rust-lang.org/1.0.0/style/ownership/builders.html#non-consuming-builders-(preferred):
+#[derive(Clone)]
+pub struct PullerBuilder {
+    conn: crate::Connection,
+    ex: String,
+    queue: String,
+    kind: lapin::ExchangeKind,
+    ex_opts: lapin::options::ExchangeDeclareOptions,
+    queue_opts: lapin::options::QueueDeclareOptions,
+    bind_opts: lapin::options::QueueBindOptions,
+    field_table: lapin::types::FieldTable,
+    verify: bool,
+    get_opts: lapin::options::BasicGetOptions,
+    ack_opts: lapin::options::BasicAckOptions,
+    rej_opts: lapin::options::BasicRejectOptions,
+    nack_opts: lapin::options::BasicNackOptions,
+}
+
+impl PullerBuilder {
+    pub fn new(conn: crate::Connection) -> Self {
+        Self {
+            conn,
+            ex: String::from(crate::DEFAULT_EXCHANGE),
+            queue: String::from(crate::DEFAULT_QUEUE),
+            kind: lapin::ExchangeKind::Direct,
+            ex_opts: lapin::options::ExchangeDeclareOptions::default(),
+            queue_opts: lapin::options::QueueDeclareOptions::default(),
+            bind_opts: lapin::options::QueueBindOptions::default(),
+            field_table: lapin::types::FieldTable::default(),
+            verify: false,
+            get_opts: lapin::options::BasicGetOptions::default(),
+            ack_opts: lapin::options::BasicAckOptions::default(),
+            rej_opts: lapin::options::BasicRejectOptions::default(),
+            nack_opts: lapin::options::BasicNackOptions::default(),
+        }
+    }
+    /// Specify the exchange name.
+    pub fn exchange(&mut self, exchange: &str) -> &mut Self {
+        self.ex = exchange.to_string();
+        self
+    }
+    /// Specify the queue name.
+    pub fn queue(&mut self, queue: &str) -> &mut Self {
+        self.queue = queue.to_string();
+        self
+    }
+    /// Verify the exchange and the queue with the passive declarations,
+    /// instead of declaring them, and fail with [Error::NotFound] in case
+    /// either of them is missing.
+    ///
+    /// [Error::NotFound]: ../error/enum.Error.html#variant.NotFound
+    pub fn verify(&mut self, verify: bool) -> &mut Self {
+        self.verify = verify;
+        self
+    }
+    /// Requeue the rejected or nacked messages.
+    pub fn requeue(&mut self, requeue: bool) -> &mut Self {
+        self.rej_opts.requeue = requeue;
+        self.nack_opts.requeue = requeue;
+        self
+    }
+    pub async fn build(&self) -> crate::Result<Puller> {
+        let opts = crate::client::QueueOptions {
+            kind: self.kind.clone(),
+            ex_opts: self.ex_opts.clone(),
+            ex_field: self.field_table.clone(),
+            queue_opts: self.queue_opts.clone(),
+            queue_field: self.field_table.clone(),
+            bind_opts: self.bind_opts.clone(),
+            bind_field: self.field_table.clone(),
+            routing_key: None,
+            verify: self.verify,
+        };
+        let (ch, q) = self.conn.queue(&self.ex, &self.queue, opts).await?;
+        Ok(Puller {
+            ch,
+            queue: q.name().to_string(),
+            get_opts: self.get_opts.clone(),
+            ack_opts: self.ack_opts.clone(),
+            rej_opts: self.rej_opts.clone(),
+            nack_opts: self.nack_opts.clone(),
+        })
+    }
+}
+
+/// A pull mode message consumer over [lapin::Channel], which fetches
+/// the messages on demand with `basic.get`, e.g. for the batch jobs.
+///
+/// [lapin::Channel]: https://docs.rs/lapin/latest/lapin/struct.Channel.html
+pub struct Puller {
+    ch: lapin::Channel,
+    queue: String,
+    get_opts: lapin::options::BasicGetOptions,
+    ack_opts: lapin::options::BasicAckOptions,
+    rej_opts: lapin::options::BasicRejectOptions,
+    nack_opts: lapin::options::BasicNackOptions,
+}
+
+impl Puller {
+    /// Fetches a single [Message], or returns `None` in case the queue
+    /// is empty.
+    ///
+    /// [Message]: ../message/struct.Message.html
+    pub async fn get(&mut self) -> crate::Result<Option<crate::Message>> {
+        let msg = self
+            .ch
+            .basic_get(&self.queue, self.get_opts.clone())
+            .await
+            .map_err(crate::Error::from)?;
+        Ok(msg.map(|msg| crate::Message::new(msg.delivery)))
+    }
+    /// Fetches up to `max` [Message]s, or less in case the queue becomes
+    /// empty.
+    ///
+    /// [Message]: ../message/struct.Message.html
+    pub async fn fetch(&mut self, max: usize) -> crate::Result<Vec<crate::Message>> {
+        let mut msgs = Vec::new();
+        while msgs.len() < max {
+            match self.get().await? {
+                Some(msg) => msgs.push(msg),
+                None => break,
+            }
+        }
+        Ok(msgs)
+    }
+    /// Fetches all the [Message]s until the queue becomes empty.
+    ///
+    /// [Message]: ../message/struct.Message.html
+    pub async fn drain(&mut self) -> crate::Result<Vec<crate::Message>> {
+        self.fetch(usize::MAX).await
+    }
+    pub async fn ack(&mut self, msg: &crate::Message) -> crate::Result<()> {
+        self.ch
+            .basic_ack(msg.delivery_tag(), self.ack_opts.clone())
+            .await
+            .map_err(crate::Error::from)
+    }
+    pub async fn reject(&mut self, msg: &crate::Message) -> crate::Result<()> {
+        self.ch
+            .basic_reject(msg.delivery_tag(), self.rej_opts.clone())
+            .await
+            .map_err(crate::Error::from)
+    }
+    pub async fn nack(&mut self, msg: &crate::Message) -> crate::Result<()> {
+        self.ch
+            .basic_nack(msg.delivery_tag(), self.nack_opts.clone())
+            .await
+            .map_err(crate::Error::from)
+    }
+}
@@ -0,0 +1,174 @@
+// SPDX-License-Identifier: Apache-2.0 AND MIT
+//! `Executor` and `Timer` traits
+use futures::future::BoxFuture;
+use std::time::Duration;
+
+/// A trait to spawn the background tasks through [Connection::spawn] on
+/// the application's runtime.
+///
+/// The executor is shared by the [Connection] across threads and spawns
+/// the `Send` tasks, so the single threaded executors, e.g. the futures
+/// [LocalPool], can't implement it.  Drive the futures, e.g. of
+/// [Consumer::run], directly on those executors instead.
+///
+/// [Connection::spawn]: ../client/struct.Connection.html#method.spawn
+/// [Connection]: ../client/struct.Connection.html
+/// [LocalPool]: https://docs.rs/futures-executor/latest/futures_executor/struct.LocalPool.html
+/// [Consumer::run]: ../consume/struct.Consumer.html#method.run
+pub trait Executor: Send + Sync {
+    fn spawn(&self, task: BoxFuture<'static, ()>);
+}
+
+/// A trait to wait for the timeouts, e.g. of the publisher confirms and
+/// the RPC replies, on the application's runtime.
+pub trait Timer: Send + Sync {
+    fn delay(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// The default [Timer] backed by [futures_timer], which works on any
+/// runtime with its own timer thread.
+///
+/// [Timer]: trait.Timer.html
+/// [futures_timer]: https://docs.rs/futures-timer
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FuturesTimer;
+
+impl Timer for FuturesTimer {
+    fn delay(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(futures_timer::Delay::new(duration))
+    }
+}
+
+/// An [Executor] over the tokio runtime.
+///
+/// [Executor]: trait.Executor.html
+#[cfg(feature = "tokio")]
+#[derive(Clone)]
+pub struct TokioExecutor(tokio::runtime::Handle);
+
+#[cfg(feature = "tokio")]
+impl TokioExecutor {
+    pub fn new(handle: tokio::runtime::Handle) -> Self {
+        Self(handle)
+    }
+    /// Returns the executor of the current tokio runtime.  It panics
+    /// outside of the runtime.
+    pub fn current() -> Self {
+        Self(tokio::runtime::Handle::current())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Executor for TokioExecutor {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        self.0.spawn(task);
+    }
+}
+
+/// A [Timer] over the tokio runtime, which requires the tokio's time
+/// driver.
+///
+/// [Timer]: trait.Timer.html
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioTimer;
+
+#[cfg(feature = "tokio")]
+impl Timer for TokioTimer {
+    fn delay(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::delay_for(duration))
+    }
+}
+
+/// An [Executor] over the async-std global executor.
+///
+/// [Executor]: trait.Executor.html
+#[cfg(feature = "async-std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStdExecutor;
+
+#[cfg(feature = "async-std")]
+impl Executor for AsyncStdExecutor {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        async_std::task::spawn(task);
+    }
+}
+
+/// A [Timer] over the async-std runtime.
+///
+/// [Timer]: trait.Timer.html
+#[cfg(feature = "async-std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStdTimer;
+
+#[cfg(feature = "async-std")]
+impl Timer for AsyncStdTimer {
+    fn delay(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+/// An [Executor] over the futures [ThreadPool].
+///
+/// [Executor]: trait.Executor.html
+/// [ThreadPool]: https://docs.rs/futures-executor/latest/futures_executor/struct.ThreadPool.html
+#[cfg(feature = "futures-executor")]
+#[derive(Clone)]
+pub struct ThreadPoolExecutor(futures_executor::ThreadPool);
+
+#[cfg(feature = "futures-executor")]
+impl ThreadPoolExecutor {
+    pub fn new(pool: futures_executor::ThreadPool) -> Self {
+        Self(pool)
+    }
+}
+
+#[cfg(feature = "futures-executor")]
+impl Executor for ThreadPoolExecutor {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        self.0.spawn_ok(task);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Timer;
+    use futures::executor::block_on;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn futures_timer() {
+        struct Test {
+            data: Duration,
+            want: Duration,
+        }
+        let tests = [
+            Test {
+                data: Duration::default(),
+                want: Duration::default(),
+            },
+            Test {
+                data: Duration::from_millis(20),
+                want: Duration::from_millis(20),
+            },
+        ];
+        for t in &tests {
+            let start = Instant::now();
+            block_on(super::FuturesTimer.delay(t.data));
+            assert!(start.elapsed() >= t.want, "{:?}", t.data);
+        }
+    }
+    #[cfg(feature = "futures-executor")]
+    #[test]
+    fn thread_pool_executor() {
+        use super::Executor;
+        use futures::channel::oneshot;
+        let pool = futures_executor::ThreadPool::new().unwrap();
+        let executor = super::ThreadPoolExecutor::new(pool);
+        let (tx, rx) = oneshot::channel();
+        executor.spawn(Box::pin(async move {
+            tx.send(42).unwrap();
+        }));
+        assert_eq!(Ok(42), block_on(rx));
+    }
+}
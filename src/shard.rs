@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: Apache-2.0 AND MIT
+//! `ShardedConsumerGroup` struct
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+
+/// The consistent hash exchange type, provided by the broker's
+/// `rabbitmq_consistent_hash_exchange` plugin.
+pub const CONSISTENT_HASH_EXCHANGE: &str = "x-consistent-hash";
+/// The binding weight of each shard queue, as the routing key.
+pub const SHARD_WEIGHT: &str = "1";
+
+/// A group of [Consumer]s, one per shard queue bound to the consistent
+/// hash exchange, so that the messages with the same routing key, e.g.
+/// the customer ID, are always processed by the same consumer in order.
+///
+/// [Consumer]: ../consume/struct.Consumer.html
+pub struct ShardedConsumerGroup {
+    conn: crate::Connection,
+    consumers: Vec<crate::Consumer>,
+}
+
+impl ShardedConsumerGroup {
+    /// Declares the `shards` number of queues, named `<queue>.<index>`,
+    /// bound to the [ConsumerBuilder]'s exchange declared as the
+    /// [CONSISTENT_HASH_EXCHANGE] and builds a [Consumer] for each.  It
+    /// fails with [Error::Config] without any shard.
+    ///
+    /// [ConsumerBuilder]: ../consume/struct.ConsumerBuilder.html
+    /// [CONSISTENT_HASH_EXCHANGE]: constant.CONSISTENT_HASH_EXCHANGE.html
+    /// [Consumer]: ../consume/struct.Consumer.html
+    /// [Error::Config]: ../error/enum.Error.html#variant.Config
+    pub async fn build(builder: &crate::ConsumerBuilder, shards: usize) -> crate::Result<Self> {
+        if builder.exchange_name() == crate::DEFAULT_EXCHANGE {
+            return Err(crate::Error::Config(String::from(
+                "sharded consumer group requires the named exchange",
+            )));
+        }
+        if builder.queue_name() == crate::EPHEMERAL_QUEUE {
+            return Err(crate::Error::Config(String::from(
+                "sharded consumer group requires the named queue",
+            )));
+        }
+        if shards == 0 {
+            return Err(crate::Error::Config(String::from(
+                "sharded consumer group requires at least one shard",
+            )));
+        }
+        let mut builder = builder.clone();
+        builder
+            .exchange_kind(lapin::ExchangeKind::Custom(String::from(
+                CONSISTENT_HASH_EXCHANGE,
+            )))
+            .routing_key(SHARD_WEIGHT);
+        let prefix = builder.queue_name().to_string();
+        let mut consumers = Vec::with_capacity(shards);
+        for i in 0..shards {
+            builder.queue(&format!("{}.{}", prefix, i));
+            consumers.push(builder.build().await?);
+        }
+        Ok(Self {
+            conn: builder.connection().clone(),
+            consumers,
+        })
+    }
+    /// Returns the shard [Consumer]s, e.g. to spawn each of them on the
+    /// executor.
+    ///
+    /// [Consumer]: ../consume/struct.Consumer.html
+    pub fn into_consumers(self) -> Vec<crate::Consumer> {
+        self.consumers
+    }
+    pub fn len(&self) -> usize {
+        self.consumers.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.consumers.is_empty()
+    }
+    /// Runs each shard [Consumer] on its own task spawned by
+    /// [Connection::spawn], until any of them fails, and then stops
+    /// the rest.  It fails with [Error::NoExecutor] without the [Executor]
+    /// provided by [Client::task_executor].
+    ///
+    /// [Consumer]: ../consume/struct.Consumer.html
+    /// [Connection::spawn]: ../client/struct.Connection.html#method.spawn
+    /// [Error::NoExecutor]: ../error/enum.Error.html#variant.NoExecutor
+    /// [Executor]: ../runtime/trait.Executor.html
+    /// [Client::task_executor]: ../client/struct.Client.html#method.task_executor
+    pub async fn run(self) -> crate::Result<()> {
+        // Dropping the stop senders, e.g. on the spawn failure, stops
+        // the already spawned consumers.
+        let mut stops = Vec::with_capacity(self.consumers.len());
+        let mut done = Vec::with_capacity(self.consumers.len());
+        for mut consumer in self.consumers {
+            let (stop_tx, stop_rx) = oneshot::channel::<()>();
+            let (tx, rx) = oneshot::channel();
+            self.conn.spawn(async move {
+                let stopped = match future::select(Box::pin(consumer.run()), stop_rx).await {
+                    Either::Left((result, _)) => {
+                        let _ = tx.send(result);
+                        false
+                    }
+                    Either::Right(_) => true,
+                };
+                if stopped {
+                    // Let the broker requeue the unacknowledged messages.
+                    let _ = consumer.close().await;
+                }
+            })?;
+            stops.push(stop_tx);
+            done.push(rx);
+        }
+        future::try_join_all(done.into_iter().map(|rx| async {
+            // The dropped task, e.g. by the executor shutdown.
+            rx.await.unwrap_or(Err(crate::Error::Other))
+        }))
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,22 @@
+// SPDX-License-Identifier: Apache-2.0 AND MIT
+//! SQLite helpers shared by the outbox and the dedup store
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Validates the `kind` table name, which should consist of the ASCII
+/// alphanumerics and the underscores, as it's formatted into the SQL.
+pub(crate) fn check_table(kind: &str, table: &str) -> crate::Result<()> {
+    if table.is_empty() || !table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(crate::Error::Config(format!(
+            "invalid {} table '{}'",
+            kind, table
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the UNIX time in milliseconds.
+pub(crate) fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
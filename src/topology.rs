@@ -0,0 +1,559 @@
+// SPDX-License-Identifier: Apache-2.0 AND MIT
+//! `Topology` struct and its exchange, queue and binding declarations
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::default::Default;
+use std::fmt;
+
+/// A declarative set of the exchanges, queues and bindings, loaded from
+/// the TOML or YAML file or built in code, and applied through
+/// [Connection::apply].
+///
+/// ```toml
+/// [[exchanges]]
+/// name = "events"
+/// kind = "topic"
+/// durable = true
+///
+/// [[queues]]
+/// name = "audit"
+/// durable = true
+/// arguments = { x-max-length = 10000 }
+///
+/// [[bindings]]
+/// source = "events"
+/// destination = "audit"
+/// routing_key = "#"
+/// ```
+///
+/// [Connection::apply]: ../client/struct.Connection.html#method.apply
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Topology {
+    pub exchanges: Vec<Exchange>,
+    pub queues: Vec<Queue>,
+    pub bindings: Vec<Binding>,
+}
+
+/// An exchange declaration.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Exchange {
+    pub name: String,
+    /// Exchange type, e.g. `direct`, `fanout`, `topic` or `headers`.
+    pub kind: String,
+    pub durable: bool,
+    pub auto_delete: bool,
+    pub internal: bool,
+    pub arguments: Arguments,
+}
+
+/// A queue declaration.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Queue {
+    pub name: String,
+    pub durable: bool,
+    pub exclusive: bool,
+    pub auto_delete: bool,
+    pub arguments: Arguments,
+}
+
+/// A binding declaration from the `source` exchange to the `destination`
+/// queue or, with the [Entity::Exchange] destination type, exchange.
+///
+/// [Entity::Exchange]: enum.Entity.html#variant.Exchange
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Binding {
+    pub source: String,
+    pub destination: String,
+    pub destination_type: Entity,
+    pub routing_key: String,
+    pub arguments: Arguments,
+}
+
+/// The declaration arguments, e.g. `x-message-ttl`.
+pub type Arguments = BTreeMap<String, Argument>;
+
+/// A declaration argument value.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Argument {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+/// A kind of the declared entity.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Entity {
+    Exchange,
+    #[default]
+    Queue,
+}
+
+/// A difference between the [Topology] and the broker's existing
+/// declaration, reported by [Connection::apply].
+///
+/// [Topology]: struct.Topology.html
+/// [Connection::apply]: ../client/struct.Connection.html#method.apply
+#[derive(Clone, Debug, PartialEq)]
+pub struct Drift {
+    pub entity: Entity,
+    pub name: String,
+    /// The broker's reason, e.g. `inequivalent arg 'durable'`.
+    pub reason: String,
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Load the topology from the TOML or YAML file, based on the file
+    /// extension, e.g. `topology.toml` or `topology.yaml`.
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> crate::Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .map_err(|err| crate::Error::Config(format!("{}: {}", path.display(), err)))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml_str(&data),
+            Some("yaml") | Some("yml") => Self::from_yaml_str(&data),
+            _ => Err(crate::Error::Config(format!(
+                "{}: unknown topology file extension",
+                path.display()
+            ))),
+        }
+    }
+    /// Load the topology from the TOML string.
+    pub fn from_toml_str(data: &str) -> crate::Result<Self> {
+        toml::from_str(data).map_err(|err| crate::Error::Config(err.to_string()))
+    }
+    /// Load the topology from the YAML string.
+    pub fn from_yaml_str(data: &str) -> crate::Result<Self> {
+        serde_yaml::from_str(data).map_err(|err| crate::Error::Config(err.to_string()))
+    }
+    pub fn exchange(&mut self, exchange: Exchange) -> &mut Self {
+        self.exchanges.push(exchange);
+        self
+    }
+    pub fn queue(&mut self, queue: Queue) -> &mut Self {
+        self.queues.push(queue);
+        self
+    }
+    pub fn binding(&mut self, binding: Binding) -> &mut Self {
+        self.bindings.push(binding);
+        self
+    }
+    /// Declare the exchanges, the queues and then the bindings over the
+    /// [Connection].  Each entity is passively declared first and, in case
+    /// it exists, the PRECONDITION_FAILED reply of the active declaration
+    /// is reported as [Drift] instead of the error.
+    ///
+    /// [Connection]: ../client/struct.Connection.html
+    /// [Drift]: struct.Drift.html
+    pub(crate) async fn apply(&self, conn: &crate::Connection) -> crate::Result<Vec<Drift>> {
+        let mut ch = conn.channel().await?;
+        let result = self.declare(conn, &mut ch).await;
+        crate::Connection::close_channel(&ch).await;
+        result
+    }
+    async fn declare(
+        &self,
+        conn: &crate::Connection,
+        ch: &mut lapin::Channel,
+    ) -> crate::Result<Vec<Drift>> {
+        let mut drifts = Vec::new();
+        for ex in &self.exchanges {
+            if let Some(drift) = ex.apply(conn, ch).await? {
+                drifts.push(drift);
+            }
+        }
+        for queue in &self.queues {
+            if let Some(drift) = queue.apply(conn, ch).await? {
+                drifts.push(drift);
+            }
+        }
+        for binding in &self.bindings {
+            binding.apply(ch).await?;
+        }
+        Ok(drifts)
+    }
+}
+
+impl Exchange {
+    pub fn new(name: &str, kind: &str) -> Self {
+        Self {
+            name: String::from(name),
+            kind: String::from(kind),
+            ..Self::default()
+        }
+    }
+    fn kind(&self) -> lapin::ExchangeKind {
+        match self.kind.as_str() {
+            "direct" => lapin::ExchangeKind::Direct,
+            "fanout" => lapin::ExchangeKind::Fanout,
+            "headers" => lapin::ExchangeKind::Headers,
+            "topic" => lapin::ExchangeKind::Topic,
+            kind => lapin::ExchangeKind::Custom(String::from(kind)),
+        }
+    }
+    fn options(&self, passive: bool) -> lapin::options::ExchangeDeclareOptions {
+        lapin::options::ExchangeDeclareOptions {
+            passive,
+            durable: self.durable,
+            auto_delete: self.auto_delete,
+            internal: self.internal,
+            nowait: false,
+        }
+    }
+    async fn apply(
+        &self,
+        conn: &crate::Connection,
+        ch: &mut lapin::Channel,
+    ) -> crate::Result<Option<Drift>> {
+        let passive = self.declare(ch, true).await;
+        let failed = passive.is_err();
+        let exists = reopen(conn, ch, failed, exists(passive)).await?;
+        let result = self.declare(ch, false).await;
+        let failed = result.is_err();
+        let drift = drift(Entity::Exchange, &self.name, exists, result);
+        reopen(conn, ch, failed, drift).await
+    }
+    async fn declare(&self, ch: &lapin::Channel, passive: bool) -> crate::Result<()> {
+        ch.exchange_declare(
+            &self.name,
+            self.kind(),
+            self.options(passive),
+            field_table(&self.arguments),
+        )
+        .await
+        .map_err(crate::Error::from)
+    }
+}
+
+impl Default for Exchange {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            kind: String::from("direct"),
+            durable: false,
+            auto_delete: false,
+            internal: false,
+            arguments: Arguments::new(),
+        }
+    }
+}
+
+impl Queue {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: String::from(name),
+            ..Self::default()
+        }
+    }
+    fn options(&self, passive: bool) -> lapin::options::QueueDeclareOptions {
+        lapin::options::QueueDeclareOptions {
+            passive,
+            durable: self.durable,
+            exclusive: self.exclusive,
+            auto_delete: self.auto_delete,
+            nowait: false,
+        }
+    }
+    async fn apply(
+        &self,
+        conn: &crate::Connection,
+        ch: &mut lapin::Channel,
+    ) -> crate::Result<Option<Drift>> {
+        let passive = self.declare(ch, true).await;
+        let failed = passive.is_err();
+        let exists = reopen(conn, ch, failed, exists(passive)).await?;
+        let result = self.declare(ch, false).await;
+        let failed = result.is_err();
+        let drift = drift(Entity::Queue, &self.name, exists, result);
+        reopen(conn, ch, failed, drift).await
+    }
+    async fn declare(&self, ch: &lapin::Channel, passive: bool) -> crate::Result<()> {
+        ch.queue_declare(
+            &self.name,
+            self.options(passive),
+            field_table(&self.arguments),
+        )
+        .await
+        .map(|_| ())
+        .map_err(crate::Error::from)
+    }
+}
+
+impl Binding {
+    pub fn new(source: &str, destination: &str, routing_key: &str) -> Self {
+        Self {
+            source: String::from(source),
+            destination: String::from(destination),
+            routing_key: String::from(routing_key),
+            ..Self::default()
+        }
+    }
+    async fn apply(&self, ch: &lapin::Channel) -> crate::Result<()> {
+        let args = field_table(&self.arguments);
+        match self.destination_type {
+            Entity::Queue => ch
+                .queue_bind(
+                    &self.destination,
+                    &self.source,
+                    &self.routing_key,
+                    lapin::options::QueueBindOptions::default(),
+                    args,
+                )
+                .await
+                .map_err(crate::Error::from),
+            Entity::Exchange => ch
+                .exchange_bind(
+                    &self.destination,
+                    &self.source,
+                    &self.routing_key,
+                    lapin::options::ExchangeBindOptions::default(),
+                    args,
+                )
+                .await
+                .map_err(crate::Error::from),
+        }
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exchange => write!(f, "exchange"),
+            Self::Queue => write!(f, "queue"),
+        }
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} '{}': {}", self.entity, self.name, self.reason)
+    }
+}
+
+impl From<bool> for Argument {
+    fn from(val: bool) -> Self {
+        Self::Bool(val)
+    }
+}
+
+impl From<i64> for Argument {
+    fn from(val: i64) -> Self {
+        Self::Int(val)
+    }
+}
+
+impl From<f64> for Argument {
+    fn from(val: f64) -> Self {
+        Self::Float(val)
+    }
+}
+
+impl From<&str> for Argument {
+    fn from(val: &str) -> Self {
+        Self::String(String::from(val))
+    }
+}
+
+impl From<&Argument> for lapin::types::AMQPValue {
+    fn from(arg: &Argument) -> Self {
+        match arg {
+            Argument::Bool(val) => Self::Boolean(*val),
+            Argument::Int(val) => Self::LongLongInt(*val),
+            Argument::Float(val) => Self::Double(*val),
+            Argument::String(val) => Self::LongString(val.as_str().into()),
+        }
+    }
+}
+
+/// Converts the declaration arguments into the lapin's `FieldTable`.
+pub fn field_table(args: &Arguments) -> lapin::types::FieldTable {
+    let mut table = lapin::types::FieldTable::default();
+    for (key, val) in args {
+        table.insert(key.as_str().into(), val.into());
+    }
+    table
+}
+
+/// Reopens the channel, closed by the broker with the soft error, in case
+/// the failure is mapped to the result, e.g. the missing entity or [Drift].
+///
+/// [Drift]: struct.Drift.html
+async fn reopen<T>(
+    conn: &crate::Connection,
+    ch: &mut lapin::Channel,
+    failed: bool,
+    result: crate::Result<T>,
+) -> crate::Result<T> {
+    if failed && result.is_ok() {
+        // Release the failed channel, in case the broker keeps it open.
+        crate::Connection::close_channel(ch).await;
+        *ch = conn.channel().await?;
+    }
+    result
+}
+
+/// Maps the passive declaration result into the existence, treating
+/// NOT_FOUND as the missing entity.
+fn exists(result: crate::Result<()>) -> crate::Result<bool> {
+    match result {
+        Ok(()) => Ok(true),
+        Err(err) if err.reply_code() == Some(crate::error::NOT_FOUND) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Maps the active declaration result of the existing entity into
+/// [Drift], in case of PRECONDITION_FAILED.
+///
+/// [Drift]: struct.Drift.html
+fn drift(
+    entity: Entity,
+    name: &str,
+    exists: bool,
+    result: crate::Result<()>,
+) -> crate::Result<Option<Drift>> {
+    match result {
+        Ok(()) => Ok(None),
+        Err(err) if exists && err.reply_code() == Some(crate::error::PRECONDITION_FAILED) => {
+            Ok(Some(Drift {
+                entity,
+                name: String::from(name),
+                reason: err.to_string(),
+            }))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Argument, Binding, Entity, Exchange, Queue, Topology};
+    use lapin::types::AMQPValue;
+
+    fn want() -> Topology {
+        let mut want = Topology::new();
+        let mut ex = Exchange::new("events", "topic");
+        ex.durable = true;
+        let mut queue = Queue::new("audit");
+        queue.durable = true;
+        queue
+            .arguments
+            .insert(String::from("x-max-length"), Argument::from(10_000));
+        queue
+            .arguments
+            .insert(String::from("x-overflow"), Argument::from("reject-publish"));
+        let mut e2e = Binding::new("events", "archive", "#");
+        e2e.destination_type = Entity::Exchange;
+        want.exchange(ex)
+            .exchange(Exchange::new("archive", "fanout"))
+            .queue(queue)
+            .binding(Binding::new("events", "audit", "#"))
+            .binding(e2e);
+        want
+    }
+    #[test]
+    fn from_toml_str() {
+        let data = r##"
+[[exchanges]]
+name = "events"
+kind = "topic"
+durable = true
+
+[[exchanges]]
+name = "archive"
+kind = "fanout"
+
+[[queues]]
+name = "audit"
+durable = true
+arguments = { x-max-length = 10000, x-overflow = "reject-publish" }
+
+[[bindings]]
+source = "events"
+destination = "audit"
+routing_key = "#"
+
+[[bindings]]
+source = "events"
+destination = "archive"
+destination_type = "exchange"
+routing_key = "#"
+"##;
+        assert_eq!(want(), Topology::from_toml_str(data).unwrap());
+    }
+    #[test]
+    fn from_yaml_str() {
+        let data = r##"
+exchanges:
+  - name: events
+    kind: topic
+    durable: true
+  - name: archive
+    kind: fanout
+queues:
+  - name: audit
+    durable: true
+    arguments:
+      x-max-length: 10000
+      x-overflow: reject-publish
+bindings:
+  - source: events
+    destination: audit
+    routing_key: "#"
+  - source: events
+    destination: archive
+    destination_type: exchange
+    routing_key: "#"
+"##;
+        assert_eq!(want(), Topology::from_yaml_str(data).unwrap());
+    }
+    #[test]
+    fn unknown_field() {
+        let data = r#"
+[[queues]]
+name = "audit"
+durabel = true
+"#;
+        assert!(Topology::from_toml_str(data).is_err());
+    }
+    #[test]
+    fn field_table() {
+        struct Test {
+            data: Argument,
+            want: AMQPValue,
+        }
+        let tests = vec![
+            Test {
+                data: Argument::from(true),
+                want: AMQPValue::Boolean(true),
+            },
+            Test {
+                data: Argument::from(60_000),
+                want: AMQPValue::LongLongInt(60_000),
+            },
+            Test {
+                data: Argument::from(0.5),
+                want: AMQPValue::Double(0.5),
+            },
+            Test {
+                data: Argument::from("lazy"),
+                want: AMQPValue::LongString("lazy".into()),
+            },
+        ];
+        for t in &tests {
+            let mut args = super::Arguments::new();
+            args.insert(String::from("x-arg"), t.data.clone());
+            let got = super::field_table(&args);
+            assert_eq!(Some(&t.want), got.inner().get("x-arg"));
+        }
+    }
+}
@@ -0,0 +1,113 @@
+// SPDX-License-Identifier: Apache-2.0 AND MIT
+//! `Transaction` struct
+
+/// An AMQP transaction over the [Consumer]'s channel, which acks the
+/// consumed [Message]s and publishes the messages through the [Producer]
+/// atomically, e.g. for the consume-transform-produce pipelines, as an
+/// alternative to the publisher confirms.
+///
+/// The broker delivers the messages to the [Consumer] as usual, but
+/// holds the acks and the publishes until [commit], or discards them
+/// on [rollback].  The rolled back messages stay unacknowledged.
+///
+/// [Consumer]: ../consume/struct.Consumer.html
+/// [Message]: ../message/struct.Message.html
+/// [Producer]: ../produce/struct.Producer.html
+/// [commit]: #method.commit
+/// [rollback]: #method.rollback
+pub struct Transaction {
+    ch: lapin::Channel,
+    ackers: Vec<crate::Acker>,
+    settled: Vec<Settled>,
+}
+
+/// The ack or the nack, recorded to the metrics on commit.
+enum Settled {
+    Acked(String, String),
+    Nacked(String, String),
+}
+
+impl Transaction {
+    /// Selects the transaction mode on the channel.
+    pub(crate) async fn select(ch: lapin::Channel) -> crate::Result<Self> {
+        ch.tx_select().await.map_err(crate::Error::from)?;
+        Ok(Self {
+            ch,
+            ackers: Vec::new(),
+            settled: Vec::new(),
+        })
+    }
+    pub async fn ack(&mut self, msg: &crate::Message) -> crate::Result<()> {
+        self.settle(msg)?;
+        self.ch
+            .basic_ack(
+                msg.delivery_tag(),
+                lapin::options::BasicAckOptions::default(),
+            )
+            .await
+            .map_err(crate::Error::from)?;
+        self.settled.push(Settled::Acked(
+            msg.exchange().to_string(),
+            msg.queue().unwrap_or_default().to_string(),
+        ));
+        Ok(())
+    }
+    pub async fn nack(&mut self, msg: &crate::Message, requeue: bool) -> crate::Result<()> {
+        self.settle(msg)?;
+        let opts = lapin::options::BasicNackOptions {
+            requeue,
+            ..lapin::options::BasicNackOptions::default()
+        };
+        self.ch
+            .basic_nack(msg.delivery_tag(), opts)
+            .await
+            .map_err(crate::Error::from)?;
+        self.settled.push(Settled::Nacked(
+            msg.exchange().to_string(),
+            msg.queue().unwrap_or_default().to_string(),
+        ));
+        Ok(())
+    }
+    /// Publishes the message to the [Producer]'s exchange and queue, with
+    /// its properties, over the transaction's channel.
+    ///
+    /// [Producer]: ../produce/struct.Producer.html
+    pub async fn publish(&mut self, producer: &crate::Producer, msg: Vec<u8>) -> crate::Result<()> {
+        let (ex, queue, opts, props) = producer.target();
+        self.ch
+            .basic_publish(ex, queue, opts, msg, props)
+            .await
+            .map_err(crate::Error::from)
+    }
+    /// Commits the acks and the publishes, and records the acks and
+    /// the nacks to the metrics.
+    pub async fn commit(&mut self) -> crate::Result<()> {
+        self.ch.tx_commit().await.map_err(crate::Error::from)?;
+        self.ackers.clear();
+        for settled in self.settled.drain(..) {
+            match settled {
+                Settled::Acked(ex, queue) => crate::metrics::acked(&ex, &queue),
+                Settled::Nacked(ex, queue) => crate::metrics::nacked(&ex, &queue),
+            }
+        }
+        Ok(())
+    }
+    /// Discards the acks and the publishes since the last [commit].
+    ///
+    /// [commit]: #method.commit
+    pub async fn rollback(&mut self) -> crate::Result<()> {
+        self.ch.tx_rollback().await.map_err(crate::Error::from)?;
+        for acker in self.ackers.drain(..) {
+            acker.unsettle();
+        }
+        self.settled.clear();
+        Ok(())
+    }
+    fn settle(&mut self, msg: &crate::Message) -> crate::Result<()> {
+        if let Some(acker) = msg.acker() {
+            acker.settle()?;
+            self.ackers.push(acker);
+        }
+        Ok(())
+    }
+}